@@ -0,0 +1,160 @@
+use std::path::{Path,PathBuf};
+use std::fs;
+
+use serde::{Deserialize,Serialize};
+
+use crate::io::files;
+use crate::merge_diff::{diff_single_conflict_with,DiffOptions};
+
+/// Name of the vanilla file in a fixture folder, without its extension, which is that of the game file it stands for
+pub const FIXTURE_BASE: &str = "base";
+/// Name of the expected merge in a fixture folder without its extension, left out when the versions should not merge
+pub const FIXTURE_EXPECTED: &str = "expected";
+/// Folder holding every mod's version in a fixture folder, merged in file name order
+pub const FIXTURE_MODS: &str = "mods";
+
+/// A merge whose right result is known, used to measure how well the automatic merge does
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub struct MergeFixture {
+    pub name: String,
+    pub base: String,
+    pub mods: Vec<String>,
+    /// The right merge, None if the mods clash and the merge should fail
+    pub expected: Option<String>,
+}
+
+impl MergeFixture {
+    /// Reads one fixture from a folder holding a `base` file, a `mods` folder and an optional `expected` file, such as `base.txt` or `base.yml`
+    pub fn open(dir: &Path) -> Result<Self,std::io::Error> {
+        let name = dir.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+        let mut mod_paths: Vec<PathBuf> = fs::read_dir(dir.join(FIXTURE_MODS))?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.is_file())
+            .collect();
+        mod_paths.sort();
+        let mods = mod_paths.iter().map(|path| read_fixture_file(path)).collect::<Result<Vec<String>,std::io::Error>>()?;
+        let base_path = find_fixture_file(dir, FIXTURE_BASE)?
+            .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, format!("{} has no {} file",dir.display(),FIXTURE_BASE)))?;
+        let expected = match find_fixture_file(dir, FIXTURE_EXPECTED)? {
+            Some(expected_path) => Some(read_fixture_file(&expected_path)?),
+            None => None,
+        };
+        Ok(MergeFixture {name, base: read_fixture_file(&base_path)?, mods, expected})
+    }
+
+    /// Reads every fixture in a corpus, one per sub folder, in name order
+    pub fn open_corpus(corpus: &Path) -> Result<Vec<Self>,std::io::Error> {
+        let mut dirs: Vec<PathBuf> = fs::read_dir(corpus)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.is_dir())
+            .collect();
+        dirs.sort();
+        dirs.iter().map(|dir| MergeFixture::open(dir)).collect()
+    }
+}
+
+// Finds the file in a fixture folder with the given name, whatever its extension
+fn find_fixture_file(dir: &Path, stem: &str) -> Result<Option<PathBuf>,std::io::Error> {
+    Ok(fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .find(|path| path.is_file() && path.file_stem().map(|name| name == stem).unwrap_or(false)))
+}
+
+fn read_fixture_file(path: &Path) -> Result<String,std::io::Error> {
+    files::fetch_file_in_path(path, true, true).ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Could not read {}",path.display())))
+}
+
+/// How the automatic merge did on a corpus of fixtures
+#[derive(Serialize,Deserialize,Clone,Debug,Default,PartialEq,Eq)]
+pub struct BenchmarkScore {
+    pub fixtures: usize,
+    /// Fixtures that merged into the expected text
+    pub merged_correctly: usize,
+    /// Fixtures that merged, but into something other than the expected text, or that should have failed
+    pub merged_wrongly: usize,
+    /// Fixtures that should not merge and did not
+    pub refused_correctly: usize,
+    /// Fixtures that should have merged but did not
+    pub refused_wrongly: usize,
+    /// Names of every fixture in the last two groups
+    pub failures: Vec<String>,
+}
+
+impl BenchmarkScore {
+    /// Share of fixtures handled correctly, from 0 to 1
+    pub fn accuracy(&self) -> f64 {
+        if self.fixtures == 0 {
+            return 0.0;
+        }
+        (self.merged_correctly + self.refused_correctly) as f64 / self.fixtures as f64
+    }
+}
+
+/// Merges every fixture with the given settings and counts how many came out right
+/// Merges must come out exactly as expected, so a merge that drops a comment or an indentation change counts as wrong
+pub fn score_fixtures(fixtures: &[MergeFixture], options: &DiffOptions) -> BenchmarkScore {
+    let mut score = BenchmarkScore {fixtures: fixtures.len(), ..BenchmarkScore::default()};
+    for fixture in fixtures {
+        let merged = diff_single_conflict_with(&fixture.base, &fixture.mods, options).text;
+        let correct = match (&merged,&fixture.expected) {
+            (Some(merged),Some(expected)) if merged == expected => {score.merged_correctly += 1; true},
+            (Some(_),_) => {score.merged_wrongly += 1; false},
+            (None,None) => {score.refused_correctly += 1; true},
+            (None,Some(_)) => {score.refused_wrongly += 1; false},
+        };
+        if !correct {
+            score.failures.push(fixture.name.clone());
+        }
+    }
+    score
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    fn fixture(name: &str, mods: &[&str], expected: Option<&str>) -> MergeFixture {
+        MergeFixture {
+            name: name.to_owned(),
+            base: "a = 1\r\nb = 2\r\nc = 3\r\nd = 4\r\ne = 5\r\n".to_owned(),
+            mods: mods.iter().map(|text| (*text).to_owned()).collect(),
+            expected: expected.map(str::to_owned),
+        }
+    }
+
+    #[test]
+    fn test_score_fixtures() {
+        let fixtures = vec![
+            fixture("one_mod", &["a = 1\r\nb = 9\r\nc = 3\r\nd = 4\r\ne = 5\r\n"], Some("a = 1\r\nb = 9\r\nc = 3\r\nd = 4\r\ne = 5\r\n")),
+            fixture("clash", &["a = 1\r\nb = 8\r\nc = 3\r\nd = 4\r\ne = 5\r\n", "a = 1\r\nb = 9\r\nc = 3\r\nd = 4\r\ne = 5\r\n"], None),
+            fixture("wrong_expectation", &["a = 1\r\nb = 9\r\nc = 3\r\nd = 4\r\ne = 5\r\n"], Some("a = 1\r\nb = 9\r\nc = 3\r\nd = 4\r\ne = 5")),
+        ];
+        let score = score_fixtures(&fixtures, &DiffOptions::default());
+        assert_eq!((score.merged_correctly,score.refused_correctly,score.merged_wrongly,score.refused_wrongly), (1,1,1,0));
+        assert_eq!(score.failures, vec!["wrong_expectation"]);
+        assert!((score.accuracy() - 2.0 / 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_fixture_corpus() {
+        // The corpus shipped with the source, so a change to the merge that gets any of it wrong fails here
+        let corpus = MergeFixture::open_corpus(&Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures").join("merges")).unwrap();
+        let score = score_fixtures(&corpus, &DiffOptions::default());
+        assert!(score.merged_correctly > 0 && score.refused_correctly > 0);
+        assert_eq!(score.failures, Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_open_corpus() {
+        let root = TempDir::new("benchmark");
+        let case = root.join("case");
+        files::write_file_with_string(case.join("base.yml"), "a\nb\n".to_owned(), false).unwrap();
+        files::write_file_with_string(case.join(FIXTURE_MODS).join("2.yml"), "a\nB\n".to_owned(), false).unwrap();
+        files::write_file_with_string(case.join(FIXTURE_MODS).join("1.yml"), "A\nb\n".to_owned(), false).unwrap();
+
+        let corpus = MergeFixture::open_corpus(root.path()).unwrap();
+        assert_eq!(corpus.len(), 1);
+        assert_eq!((corpus[0].name.as_str(),corpus[0].mods[0].as_str(),corpus[0].expected.as_ref()), ("case","A\r\nb\r\n",None));
+    }
+}
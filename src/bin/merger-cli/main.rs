@@ -0,0 +1,1007 @@
+mod resolver;
+
+use paradoxmerger::{ModInfo,ModPack,ModReader,ModStatus,generate_enabled_mod_list,generate_entire_mod_list,set_entire_mod_list,files_in_vanilla};
+use paradoxmerger::{generate_patch,preview_merge,restore_patch_backup,extract_all_files,unresolved_conflicts};
+use paradoxmerger::{ConflictMatrix,MergeOutcome,MergeReport,PlaysetStore,ProgressEvent,ProgressReporter,ProgressStage,ResolutionPolicy,ResolutionStore};
+use paradoxmerger::{score_fixtures,BenchmarkScore,DiffOptions,MergeFixture};
+use paradoxmerger::configs::{ArgOptions,ConfigOptions,PatchFormat,parse_user_config,user_config_path,store_user_configs,generate_default_configs};
+
+use std::path::{PathBuf,Path};
+use std::io::Write;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize,Ordering};
+use clap::{Arg,ArgAction,ArgMatches,Command,value_parser};
+use serde::Serialize;
+use serde_json::{Value,json};
+
+use resolver::{Decision,Resolver};
+
+const BAR_WIDTH: usize = 40;
+
+type CliResult = Result<(),Box<dyn std::error::Error>>;
+/// Structured result of a command, which becomes the `data` field of the JSON output
+type CommandResult = Result<Value,Box<dyn std::error::Error>>;
+
+/// Draws a progress bar on stderr, printing warnings and, if verbose, every processed item
+/// Warnings are also kept, so they can be included in JSON output
+struct TerminalProgress {
+    verbose: bool,
+    total: AtomicUsize,
+    done: AtomicUsize,
+    warnings: Mutex<Vec<String>>,
+}
+
+impl TerminalProgress {
+    fn new(verbose: bool) -> Self {
+        TerminalProgress {verbose, total: AtomicUsize::new(0), done: AtomicUsize::new(0), warnings: Mutex::new(Vec::new())}
+    }
+
+    fn warnings(&self) -> Vec<String> {
+        match self.warnings.lock() {
+            Ok(warnings) => warnings.clone(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    fn draw_bar(&self, label: &str) {
+        let total = self.total.load(Ordering::Relaxed).max(1);
+        let done = self.done.load(Ordering::Relaxed).min(total);
+        let filled = BAR_WIDTH * done / total;
+        eprint!("\r{:<12} [{}{}] {}/{}", label, "#".repeat(filled), " ".repeat(BAR_WIDTH - filled), done, total);
+        let _ = std::io::stderr().flush();
+    }
+}
+
+impl ProgressReporter for TerminalProgress {
+    fn report(&self, event: ProgressEvent) {
+        match event {
+            ProgressEvent::Started { stage, total } => {
+                self.total.store(total, Ordering::Relaxed);
+                self.done.store(0, Ordering::Relaxed);
+                self.draw_bar(&format!("{:?}",stage));
+            },
+            ProgressEvent::Processed { stage, path, mod_name } => {
+                self.done.fetch_add(1, Ordering::Relaxed);
+                if self.verbose {
+                    match mod_name {
+                        Some(name) => eprintln!("\r{} ({})",path.display(),name),
+                        None => eprintln!("\r{}",path.display()),
+                    }
+                }
+                self.draw_bar(&format!("{:?}",stage));
+            },
+            ProgressEvent::Conflict { path, mods } => {
+                if self.verbose {
+                    eprintln!("\rAttempting to merge: {} {:?}",path.display(),mods);
+                }
+            },
+            ProgressEvent::Warning { message, .. } => {
+                eprintln!("\r{}",message);
+                if let Ok(mut warnings) = self.warnings.lock() {
+                    warnings.push(message.to_owned());
+                }
+            },
+            ProgressEvent::Finished { .. } => eprintln!(),
+        }
+    }
+}
+
+/// How results are written to stdout
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+enum OutputFormat {
+    /// Lines for people to read
+    Text,
+    /// A single JSON document, as described in docs/cli-json.md
+    Json,
+}
+
+/// Version of the JSON document layout, raised whenever a field is removed or changes meaning
+const SCHEMA_VERSION: u32 = 1;
+
+/// Everything a command needs from the command line
+struct Session<'a> {
+    matches: &'a ArgMatches,
+    format: OutputFormat,
+    progress: TerminalProgress,
+}
+
+impl Session<'_> {
+    fn text(&self) -> bool {
+        self.format == OutputFormat::Text
+    }
+}
+
+fn main() {
+    let matches = cli().get_matches();
+    let format = match matches.get_one::<String>("format").map(|f| f.as_str()) {
+        Some("json") => OutputFormat::Json,
+        _ => OutputFormat::Text,
+    };
+    let session = Session {matches: &matches, format, progress: TerminalProgress::new(matches.get_flag("verbose"))};
+    let (command,result) = match matches.subcommand() {
+        Some((command,sub)) => (command, run_command(&session, command, sub)),
+        None => unreachable!("clap requires a subcommand"),
+    };
+
+    match format {
+        OutputFormat::Text => {
+            if let Err(e) = &result {
+                eprintln!("Error: {}",e);
+            }
+        },
+        OutputFormat::Json => {
+            let (status,data,error) = match &result {
+                Ok(data) => ("ok", data.clone(), Value::Null),
+                Err(e) => ("error", Value::Null, Value::String(e.to_string())),
+            };
+            let document = json!({
+                "schema_version": SCHEMA_VERSION,
+                "command": command,
+                "status": status,
+                "data": data,
+                "warnings": session.progress.warnings(),
+                "error": error,
+            });
+            match serde_json::to_string_pretty(&document) {
+                Ok(text) => println!("{}",text),
+                Err(e) => eprintln!("Error: {}",e),
+            }
+        },
+    }
+    if result.is_err() {
+        std::process::exit(1);
+    }
+}
+
+fn run_command(session: &Session, command: &str, sub: &ArgMatches) -> CommandResult {
+    match command {
+        "scan" => run_scan(session, sub),
+        "conflicts" => run_conflicts(session, sub),
+        "merge" => run_merge(session, sub),
+        "resolve" => run_resolve(session, sub),
+        "resolutions" => run_resolutions(session, sub),
+        "extract" => run_extract(session, sub),
+        "restore" => run_restore(session, sub),
+        "list-mods" => run_list_mods(session, sub),
+        "enable" => run_toggle(session, sub, true),
+        "disable" => run_toggle(session, sub, false),
+        "playsets" => run_playsets(session, sub),
+        "config" => run_config(session, sub),
+        "report" => run_report(session, sub),
+        "benchmark" => run_benchmark(session, sub),
+        _ => unreachable!("clap only accepts known commands"),
+    }
+}
+
+/// Describes every subcommand and the options they share
+fn cli() -> Command {
+    let patch_name = || Arg::new("patch_name")
+        .required(true)
+        .value_name("PATCH_NAME")
+        .help("name of the generated mod");
+    let output = || Arg::new("output")
+        .short('o')
+        .long("output")
+        .value_name("OUTPUT_DIR")
+        .value_parser(value_parser!(PathBuf))
+        .help("directory to write the patch and its .mod file into, defaults to current directory");
+    let game_dir = || Arg::new("game-dir")
+        .long("game-dir")
+        .action(ArgAction::SetTrue)
+        .conflicts_with("output")
+        .help("write the patch and its .mod file into the game's mod directory");
+    let zip = || Arg::new("zip")
+        .short('z')
+        .long("zip")
+        .action(ArgAction::SetTrue)
+        .help("package the generated patch as a zip archive instead of a folder");
+    let mod_names = || Arg::new("mods")
+        .required(true)
+        .num_args(1..)
+        .value_name("MOD")
+        .help("mod names or descriptor files, such as mod/ugc_123.mod");
+    let resolutions_file = || Arg::new("file")
+        .required(true)
+        .value_name("FILE")
+        .value_parser(value_parser!(PathBuf))
+        .help("JSON file of resolutions");
+    let diff_values = |id: &'static str, value_name: &'static str, help: &'static str| Arg::new(id)
+        .long(id)
+        .value_name(value_name)
+        .value_delimiter(',')
+        .help(help);
+    let playset_name = || Arg::new("name")
+        .required(true)
+        .value_name("NAME")
+        .help("name of the playset");
+
+    Command::new("merger-cli")
+    .version(env!("CARGO_PKG_VERSION"))
+    .about("Merges some mods together automatically sometimes.")
+    .author("Parker Okonek")
+    .subcommand_required(true)
+    .arg_required_else_help(true)
+    .arg(Arg::new("config")
+        .short('c')
+        .long("config")
+        .global(true)
+        .value_name("CONFIG_FILE")
+        .value_parser(value_parser!(PathBuf))
+        .help("configuration file to load, defaults to merger.toml in the user's config directory"))
+    .arg(Arg::new("game")
+        .short('g')
+        .long("game")
+        .global(true)
+        .value_name("GAME_ID")
+        .help("game in the config to use, defaults to the first one"))
+    .arg(Arg::new("format")
+        .long("format")
+        .global(true)
+        .value_name("FORMAT")
+        .value_parser(["text", "json"])
+        .default_value("text")
+        .help("print results as text, or as a single JSON document described in docs/cli-json.md"))
+    .arg(Arg::new("verbose")
+        .short('v')
+        .long("verbose")
+        .global(true)
+        .action(ArgAction::SetTrue)
+        .help("print information about processed mods"))
+    .subcommand(Command::new("scan")
+        .about("read every enabled mod and list the files it contains"))
+    .subcommand(Command::new("conflicts")
+        .about("list files changed by more than one enabled mod")
+        .arg(Arg::new("matrix")
+            .long("matrix")
+            .value_name("MATRIX_FILE")
+            .value_parser(value_parser!(PathBuf))
+            .help("also write the files shared by each pair of mods, as CSV if the file ends in .csv and as JSON otherwise")))
+    .subcommand(Command::new("merge")
+        .about("merge every conflicting file into a patch mod")
+        .arg(patch_name())
+        .arg(Arg::new("extract")
+            .short('x')
+            .long("extract")
+            .action(ArgAction::SetTrue)
+            .help("extract all non-conflicting files into the patch as well"))
+        .arg(Arg::new("dry-run")
+            .short('d')
+            .long("dry-run")
+            .action(ArgAction::SetTrue)
+            .help("merge in memory and show what would merge cleanly, without writing anything"))
+        .arg(Arg::new("jobs")
+            .short('j')
+            .long("jobs")
+            .value_name("THREADS")
+            .value_parser(value_parser!(usize))
+            .help("number of files to merge in parallel, defaults to one per core"))
+        .arg(Arg::new("incremental")
+            .short('i')
+            .long("incremental")
+            .action(ArgAction::SetTrue)
+            .help("copy conflicts whose inputs have not changed since the last patch instead of merging them again"))
+        .arg(Arg::new("report")
+            .long("report")
+            .value_name("REPORT_FILE")
+            .value_parser(value_parser!(PathBuf))
+            .help("write a report of every merged conflict, as an HTML page if the file ends in .html and as JSON otherwise"))
+        .arg(Arg::new("patches")
+            .long("patches")
+            .action(ArgAction::SetTrue)
+            .help("write a .patch file for every merged conflict, showing the output against vanilla and against each mod"))
+        .arg(Arg::new("overrides")
+            .long("overrides")
+            .value_name("OVERRIDE_DIR")
+            .value_parser(value_parser!(PathBuf))
+            .help("folder of hand-written files, laid out like a mod, that replace the merged output, instead of the game's override_dir"))
+        .arg(zip())
+        .arg(output())
+        .arg(game_dir()))
+    .subcommand(Command::new("resolve")
+        .about("walk through the hunks that could not be merged automatically and save how to resolve each one")
+        .arg(Arg::new("jobs")
+            .short('j')
+            .long("jobs")
+            .value_name("THREADS")
+            .value_parser(value_parser!(usize))
+            .help("number of files to merge in parallel, defaults to one per core"))
+        .arg(Arg::new("policy")
+            .long("policy")
+            .value_name("POLICY")
+            .value_parser(["vanilla","first","last","both"])
+            .help("resolve every remaining hunk without asking, keeping vanilla, the first or last mod's version, or every version in load order")))
+    .subcommand(Command::new("resolutions")
+        .about("list, share or forget the saved hunk resolutions")
+        .subcommand_required(true)
+        .subcommand(Command::new("list")
+            .about("list every file with saved resolutions"))
+        .subcommand(Command::new("export")
+            .about("copy the saved resolutions into a file to share")
+            .arg(resolutions_file()))
+        .subcommand(Command::new("import")
+            .about("add the resolutions from a shared file")
+            .arg(resolutions_file())
+            .arg(Arg::new("overwrite")
+                .long("overwrite")
+                .action(ArgAction::SetTrue)
+                .help("replace our resolution of a hunk when the file resolves it differently")))
+        .subcommand(Command::new("forget")
+            .about("delete every saved resolution for a file")
+            .arg(Arg::new("path")
+                .required(true)
+                .value_name("PATH")
+                .value_parser(value_parser!(PathBuf))
+                .help("path of the file relative to the game directory, such as common/traits.txt"))))
+    .subcommand(Command::new("extract")
+        .about("copy every file of every enabled mod into a single mod, later mods replacing earlier ones")
+        .arg(patch_name())
+        .arg(zip())
+        .arg(output())
+        .arg(game_dir()))
+    .subcommand(Command::new("restore")
+        .about("restore the patch replaced by the last successful merge")
+        .arg(patch_name())
+        .arg(output())
+        .arg(game_dir()))
+    .subcommand(Command::new("list-mods")
+        .about("list enabled mods in load order")
+        .arg(Arg::new("all")
+            .short('a')
+            .long("all")
+            .action(ArgAction::SetTrue)
+            .help("list disabled mods as well")))
+    .subcommand(Command::new("enable")
+        .about("enable mods in the launcher settings")
+        .arg(mod_names()))
+    .subcommand(Command::new("disable")
+        .about("disable mods in the launcher settings")
+        .arg(mod_names()))
+    .subcommand(Command::new("playsets")
+        .about("save and switch between named sets of enabled mods")
+        .subcommand_required(true)
+        .subcommand(Command::new("list")
+            .about("list the saved playsets for the game"))
+        .subcommand(Command::new("save")
+            .about("save the currently enabled mods as a playset")
+            .arg(playset_name()))
+        .subcommand(Command::new("load")
+            .about("enable exactly the mods in a playset")
+            .arg(playset_name()))
+        .subcommand(Command::new("delete")
+            .about("delete a saved playset")
+            .arg(playset_name())))
+    .subcommand(Command::new("config")
+        .about("create, print or edit the configuration file")
+        .subcommand_required(true)
+        .subcommand(Command::new("init")
+            .about("detect installed games and write a default configuration")
+            .arg(Arg::new("force")
+                .long("force")
+                .action(ArgAction::SetTrue)
+                .help("replace an existing configuration file")))
+        .subcommand(Command::new("show")
+            .about("print the configuration file"))
+        .subcommand(Command::new("edit")
+            .about("open the configuration file in $VISUAL or $EDITOR")))
+    .subcommand(Command::new("report")
+        .about("summarize a JSON merge report, or render it as an HTML page")
+        .arg(Arg::new("report_file")
+            .required(true)
+            .value_name("REPORT_FILE")
+            .value_parser(value_parser!(PathBuf))
+            .help("JSON report written by merge --report"))
+        .arg(Arg::new("html")
+            .long("html")
+            .value_name("HTML_FILE")
+            .value_parser(value_parser!(PathBuf))
+            .help("write the report as a standalone HTML page")))
+    .subcommand(Command::new("benchmark")
+        .about("score the automatic merge on merges with known results, for every combination of the given settings")
+        .arg(Arg::new("corpus")
+            .required(true)
+            .value_name("CORPUS_DIR")
+            .value_parser(value_parser!(PathBuf))
+            .help("folder with a sub folder per merge, holding a base file, a mods folder and, unless the merge should fail, an expected file"))
+        .arg(Arg::new("path")
+            .long("path")
+            .value_name("PATH")
+            .value_parser(value_parser!(PathBuf))
+            .help("start from the game's settings for this file, such as common/traits.txt, instead of the defaults"))
+        .arg(diff_values("max-fuzz", "LINES", "context lines on either side of a hunk that other mods may change, comma separated to try several").value_parser(value_parser!(usize)))
+        .arg(diff_values("context-lines", "LINES", "lines on either side of a hunk checked for changes by other mods, comma separated to try several").value_parser(value_parser!(usize)))
+        .arg(diff_values("ignore-comments", "BOOL", "whether comments are left out when comparing lines, true, false or both").value_parser(value_parser!(bool)))
+        .arg(diff_values("ignore-indentation", "BOOL", "whether indentation is left out when comparing lines, true, false or both").value_parser(value_parser!(bool))))
+}
+
+/// Builds the program options from the global options and, for commands that write a patch, the patch options
+fn arg_options(matches: &ArgMatches, sub: &ArgMatches) -> ArgOptions {
+    let config_path = matches.get_one::<PathBuf>("config").cloned().unwrap_or_default();
+    let game_id = matches.get_one::<String>("game").cloned().unwrap_or_default();
+    let verbose = matches.get_flag("verbose");
+    let patch_name = sub_value::<String>(sub, "patch_name").unwrap_or_else(|| "merged_patch".to_owned());
+    let extract = sub_flag(sub, "extract");
+    let dry_run = sub_flag(sub, "dry-run");
+    let jobs = sub_value::<usize>(sub, "jobs").unwrap_or(0);
+    let patch_format = if sub_flag(sub, "zip") { PatchFormat::Zip } else { PatchFormat::Folder };
+    let output_dir = sub_value::<PathBuf>(sub, "output").unwrap_or_default();
+    let incremental = sub_flag(sub, "incremental");
+    let review_patches = sub_flag(sub, "patches");
+
+    ArgOptions::new(config_path,extract,dry_run,verbose,game_id,patch_name)
+        .with_jobs(jobs)
+        .with_patch_format(patch_format)
+        .with_output_dir(output_dir)
+        .with_incremental(incremental)
+        .with_review_patches(review_patches)
+        .with_resolutions(Some(ResolutionStore::default_path()))
+}
+
+// Subcommands only define the options they use, so look up the rest without panicking
+fn sub_flag(sub: &ArgMatches, id: &str) -> bool {
+    matches!(sub.try_get_one::<bool>(id), Ok(Some(true)))
+}
+
+fn sub_value<T: Clone + Send + Sync + 'static>(sub: &ArgMatches, id: &str) -> Option<T> {
+    sub.try_get_one::<T>(id).ok().flatten().cloned()
+}
+
+/// Reads the game's configuration, then points the output at the game's mod directory if asked to
+fn load_config(session: &Session, sub: &ArgMatches) -> Result<(ConfigOptions,ArgOptions),Box<dyn std::error::Error>> {
+    let args = arg_options(session.matches, sub);
+    let config = parse_user_config(&args,true)?;
+    let args = if sub_flag(sub, "game-dir") {
+        let game_mod_dir = config.game_mod_dir();
+        args.with_output_dir(game_mod_dir)
+    } else {
+        args
+    };
+    Ok((config,args))
+}
+
+/// Reads the enabled mods, or every mod, and finds the conflicts between the enabled ones
+fn load_mod_pack(config: &ConfigOptions, all_mods: bool, find_conflicts: bool, progress: &dyn ProgressReporter) -> ModPack {
+    let mut mod_pack = ModPack::default().restrict_paths(&config.valid_paths).restrict_extensions(&config.valid_extensions);
+    let mod_list: Vec<ModInfo> = if all_mods {
+        generate_entire_mod_list(&config.mod_path,config.new_launcher,progress)
+    } else {
+        generate_enabled_mod_list(&config.mod_path,config.new_launcher,progress)
+    };
+    if find_conflicts {
+        let vanilla = files_in_vanilla(config);
+        let val_ref: Vec<&Path> = vanilla.iter().map(|x| x.as_path()).collect();
+        mod_pack.register_vanilla(&val_ref);
+    }
+    mod_pack.add_mods(&mod_list, find_conflicts, find_conflicts, progress);
+    mod_pack
+}
+
+/// A mod and every file it contains, as listed by `scan`
+#[derive(Serialize)]
+struct ScannedMod<'a> {
+    name: &'a str,
+    descriptor: &'a Path,
+    archive: bool,
+    files: Vec<&'a String>,
+}
+
+fn run_scan(session: &Session, sub: &ArgMatches) -> CommandResult {
+    let (config,_args) = load_config(session, sub)?;
+    let mod_pack = load_mod_pack(&config, false, false, &session.progress);
+    let load_order = mod_pack.load_order();
+    let mut scanned = Vec::new();
+    for status in &load_order {
+        if let Some(mod_info) = mod_pack.get_mod(status.name()) {
+            let mut files: Vec<&String> = mod_info.get_filetree().iter().collect();
+            files.sort();
+            scanned.push(ScannedMod {name: mod_info.get_name(), descriptor: status.mod_file(), archive: mod_info.is_zip(), files});
+        }
+    }
+
+    if session.text() {
+        for scanned_mod in &scanned {
+            let kind = if scanned_mod.archive { "zip" } else { "folder" };
+            println!("{} ({}, {} files)",scanned_mod.name,kind,scanned_mod.files.len());
+            if session.progress.verbose {
+                for file in &scanned_mod.files {
+                    println!("    {}",file);
+                }
+            }
+        }
+    }
+    Ok(json!({ "mods": scanned }))
+}
+
+fn run_conflicts(session: &Session, sub: &ArgMatches) -> CommandResult {
+    let (config,_args) = load_config(session, sub)?;
+    let mod_pack = load_mod_pack(&config, false, true, &session.progress);
+
+    if session.text() {
+        if mod_pack.list_conflicts().is_empty() {
+            println!("No mod conflicts were found");
+        }
+        for conflict in mod_pack.list_conflicts() {
+            println!("{}",conflict.path().display());
+            println!("    {}",conflict.list_mods().join(", "));
+        }
+    }
+
+    let matrix = match sub.get_one::<PathBuf>("matrix") {
+        Some(matrix_path) => {
+            let matrix = mod_pack.conflict_matrix(&config.unmergeable_paths);
+            write_matrix(&matrix, matrix_path)?;
+            if session.text() {
+                print_matrix(&matrix);
+                eprintln!("Conflict matrix written to {}",matrix_path.display());
+            }
+            Some(matrix)
+        },
+        None => None,
+    };
+    Ok(json!({ "conflicts": mod_pack.list_conflicts(), "matrix": matrix }))
+}
+
+fn run_merge(session: &Session, sub: &ArgMatches) -> CommandResult {
+    let (config,args) = load_config(session, sub)?;
+    let config = match sub.get_one::<PathBuf>("overrides") {
+        Some(override_dir) => config.with_override_dir(Some(override_dir.clone())),
+        None => config,
+    };
+    let mod_pack = load_mod_pack(&config, false, true, &session.progress);
+
+    let reader = ModReader::new();
+    if args.dry_run {
+        let report = preview_merge(&config, &args, &mod_pack, &reader, &session.progress)
+            .map_err(|e| format!("Dry run failed: {}",e))?;
+        if session.text() {
+            print_preview(&report);
+            print_stale_overrides(&report);
+        }
+        return Ok(json!({ "dry_run": true, "conflicts": mod_pack.list_conflicts(), "report": report }));
+    }
+
+    if args.extract{
+        eprintln!("Extracting all files, this could take some time.");
+    }
+
+    let report = generate_patch(&config, &args, &mod_pack, &reader, &session.progress)
+        .map_err(|e| format!("Patch generation failed: {}",e))?;
+    if let Some(report_path) = sub.get_one::<PathBuf>("report") {
+        write_report(&report, report_path)?;
+        eprintln!("Merge report written to {}",report_path.display());
+    }
+    if session.text() {
+        let num_mods: f32 = mod_pack.list_conflicts().len() as f32;
+        if !mod_pack.list_conflicts().is_empty() {
+            let results = 100f32 * (report.successful() as f32) / num_mods;
+            println!("{}% of merges completed successfully",results);
+            println!("Unmerged mod files output to folder {}_bad",args.folder_name());
+        } else {
+            println!("No mod conflicts were found");
+        }
+        print_stale_overrides(&report);
+    }
+    Ok(json!({ "dry_run": false, "conflicts": mod_pack.list_conflicts(), "report": report }))
+}
+
+fn run_resolve(session: &Session, sub: &ArgMatches) -> CommandResult {
+    let (config,args) = load_config(session, sub)?;
+    let mod_pack = load_mod_pack(&config, false, true, &session.progress);
+    let store_path = ResolutionStore::default_path();
+    let mut store = ResolutionStore::open(&store_path)?;
+    let reader = ModReader::new();
+    let conflicts = unresolved_conflicts(&config, &args, &mod_pack, &reader, &store, &session.progress)
+        .map_err(|e| format!("Could not merge the conflicting files: {}",e))?;
+
+    // With JSON output, stdout is kept for the result document
+    let output: Box<dyn Write> = if session.text() { Box::new(std::io::stdout()) } else { Box::new(std::io::stderr()) };
+    let mut resolver = Resolver::new(std::io::stdin().lock(), output, resolver::terminal_width());
+    let policy = match sub.get_one::<String>("policy").map(|p| p.as_str()) {
+        Some("vanilla") => Some(ResolutionPolicy::Vanilla),
+        Some("first") => Some(ResolutionPolicy::FirstMod),
+        Some("last") => Some(ResolutionPolicy::LastMod),
+        Some("both") => Some(ResolutionPolicy::AllInOrder),
+        _ => None,
+    };
+    let total: usize = conflicts.iter().map(|conflict| store.unresolved(&conflict.path, &conflict.chunks).len()).sum();
+    let mut number = 0;
+    let mut resolved = 0;
+    'files: for conflict in &conflicts {
+        for hunk in store.unresolved(&conflict.path, &conflict.chunks) {
+            number += 1;
+            // The same hunk can turn up twice in a file, in which case it is already settled
+            if store.get(&conflict.path, hunk).is_some() {
+                continue;
+            }
+            let decision = match policy {
+                Some(policy) => Decision::Resolve(policy.resolve(hunk, &conflict.mods)),
+                None => resolver.ask(conflict, hunk, number, total)?,
+            };
+            match decision {
+                Decision::Resolve(resolution) => {
+                    store.insert(&conflict.path, hunk, resolution);
+                    // Save each choice straight away so quitting part way through loses nothing
+                    if policy.is_none() {
+                        store.save(&store_path)?;
+                    }
+                    resolved += 1;
+                },
+                Decision::Skip => (),
+                Decision::Quit => break 'files,
+            }
+        }
+    }
+
+    if policy.is_some() {
+        store.save(&store_path)?;
+    }
+
+    // Files the automatic merge refused without any clashing hunk have nothing to resolve, but still can't be merged
+    let remaining: Vec<&Path> = conflicts.iter()
+        .filter(|conflict| store.apply(&conflict.path, &conflict.chunks).is_none())
+        .map(|conflict| conflict.path.as_path())
+        .collect();
+    let by_hand = conflicts.iter().filter(|conflict| store.unresolved(&conflict.path, &conflict.chunks).is_empty() && store.apply(&conflict.path, &conflict.chunks).is_none()).count();
+    if session.text() {
+        if conflicts.is_empty() {
+            println!("Every conflict can be merged, there is nothing to resolve");
+        } else {
+            println!("Saved {} resolutions to {}, merge again to apply them",resolved,store_path.display());
+            if remaining.len() > by_hand {
+                println!("{} files still have unresolved hunks",remaining.len() - by_hand);
+            }
+            if by_hand > 0 {
+                println!("{} files were refused by the automatic merge without any clashing hunk, they need merging by hand",by_hand);
+            }
+        }
+    }
+    Ok(json!({ "resolutions": store_path, "resolved": resolved, "remaining": remaining }))
+}
+
+fn run_resolutions(session: &Session, sub: &ArgMatches) -> CommandResult {
+    let store_path = ResolutionStore::default_path();
+    let mut store = ResolutionStore::open(&store_path)?;
+    match sub.subcommand() {
+        Some(("list", _)) => {
+            let files: Vec<Value> = store.files().into_iter().map(|(path,hunks)| json!({ "path": path, "hunks": hunks })).collect();
+            if session.text() {
+                for (path,hunks) in store.files() {
+                    println!("{}\t{} hunks",path.display(),hunks);
+                }
+                println!("{} resolutions saved in {}",store.len(),store_path.display());
+            }
+            Ok(json!({ "path": store_path, "files": files }))
+        },
+        Some(("export", export)) => {
+            let file = export.get_one::<PathBuf>("file").ok_or("An export file is required")?;
+            store.save(file)?;
+            if session.text() {
+                println!("Exported {} resolutions to {}",store.len(),file.display());
+            }
+            Ok(json!({ "path": file, "resolutions": store.len() }))
+        },
+        Some(("import", import)) => {
+            let file = import.get_one::<PathBuf>("file").ok_or("An import file is required")?;
+            if !file.exists() {
+                return Err(format!("{} does not exist",file.display()).into());
+            }
+            let (added,replaced) = store.import(ResolutionStore::open(file)?, import.get_flag("overwrite"));
+            store.save(&store_path)?;
+            if session.text() {
+                println!("Imported {} new and {} changed resolutions from {}",added,replaced,file.display());
+            }
+            Ok(json!({ "path": file, "added": added, "replaced": replaced }))
+        },
+        Some(("forget", forget)) => {
+            let path = forget.get_one::<PathBuf>("path").ok_or("A file path is required")?;
+            let removed = store.remove_file(path);
+            if removed == 0 {
+                return Err(format!("No resolutions are saved for {}",path.display()).into());
+            }
+            store.save(&store_path)?;
+            if session.text() {
+                println!("Forgot {} resolutions for {}",removed,path.display());
+            }
+            Ok(json!({ "path": path, "removed": removed }))
+        },
+        _ => unreachable!("clap only accepts known resolutions commands"),
+    }
+}
+
+fn run_extract(session: &Session, sub: &ArgMatches) -> CommandResult {
+    let (config,args) = load_config(session, sub)?;
+    let mod_pack = load_mod_pack(&config, false, false, &session.progress);
+    let reader = ModReader::new();
+    let destination = args.output_root()?;
+    let to_zip = args.patch_format == PatchFormat::Zip;
+    eprintln!("Extracting all files, this could take some time.");
+    extract_all_files(&mod_pack, &args, &config, to_zip, &destination, &reader, &session.progress);
+    let output = if to_zip { destination.join(args.folder_name() + ".zip") } else { destination.join(args.folder_name()) };
+    if session.text() {
+        println!("Extracted every enabled mod to {}",output.display());
+    }
+    Ok(json!({ "output": output }))
+}
+
+fn run_restore(session: &Session, sub: &ArgMatches) -> CommandResult {
+    let (_config,args) = load_config(session, sub)?;
+    let restored = restore_patch_backup(&args)?;
+    if session.text() {
+        if restored {
+            println!("Restored the previous version of {}",args.folder_name());
+        } else {
+            println!("There is no backup of {} to restore",args.folder_name());
+        }
+    }
+    Ok(json!({ "patch": args.folder_name(), "restored": restored }))
+}
+
+fn run_list_mods(session: &Session, sub: &ArgMatches) -> CommandResult {
+    let (config,_args) = load_config(session, sub)?;
+    let mod_pack = load_mod_pack(&config, sub.get_flag("all"), false, &session.progress);
+    let load_order = mod_pack.load_order();
+    if session.text() {
+        for status in &load_order {
+            let marker = if status.status() { "[x]" } else { "[ ]" };
+            println!("{} {} ({})",marker,status.name(),status.mod_file().display());
+        }
+    }
+    Ok(json!({ "mods": load_order }))
+}
+
+fn run_toggle(session: &Session, sub: &ArgMatches, enable: bool) -> CommandResult {
+    let (config,_args) = load_config(session, sub)?;
+    let mut mod_pack = load_mod_pack(&config, true, false, &session.progress);
+
+    let mut changed: Vec<String> = Vec::new();
+    for query in sub.get_many::<String>("mods").into_iter().flatten() {
+        let status = find_mod(&mod_pack, query).ok_or_else(|| format!("No mod named {}",query))?;
+        if status.status() != enable {
+            mod_pack.toggle_by_token(status.special_number());
+        }
+        changed.push(status.name().to_owned());
+        if session.text() {
+            println!("{} {}",if enable { "Enabled" } else { "Disabled" },query);
+        }
+    }
+    let load_order = mod_pack.load_order();
+    set_entire_mod_list(&config.mod_path, config.new_launcher, &load_order, &session.progress)?;
+    let changed: Vec<&ModStatus> = load_order.iter().filter(|status| changed.iter().any(|name| name == status.name())).collect();
+    Ok(json!({ "mods": changed }))
+}
+
+/// Finds a mod by its name or by its descriptor file
+fn find_mod(mod_pack: &ModPack, query: &str) -> Option<ModStatus> {
+    mod_pack.load_order().into_iter()
+        .find(|status| status.name() == query || status.mod_file() == Path::new(query))
+}
+
+fn run_playsets(session: &Session, sub: &ArgMatches) -> CommandResult {
+    let (action,action_matches) = sub.subcommand().ok_or("A playset command is required")?;
+    let (config,_args) = load_config(session, action_matches)?;
+    let store_path = PlaysetStore::default_path();
+    let mut store = PlaysetStore::open(&store_path)?;
+    let game_id = config.game_name.clone();
+    let name = action_matches.try_get_one::<String>("name").ok().flatten();
+
+    match (action,name) {
+        ("list",_) => {
+            let names = store.list(&game_id);
+            if session.text() {
+                for name in &names {
+                    println!("{}",name);
+                }
+            }
+            Ok(json!({ "game": game_id, "playsets": names }))
+        },
+        ("save",Some(name)) => {
+            let mod_pack = load_mod_pack(&config, true, false, &session.progress);
+            store.insert(&game_id, name, &mod_pack.load_order());
+            store.save(&store_path)?;
+            if session.text() {
+                println!("Saved playset {}",name);
+            }
+            Ok(json!({ "game": game_id, "playset": name, "mods": store.get(&game_id, name) }))
+        },
+        ("load",Some(name)) => {
+            let mut mod_pack = load_mod_pack(&config, true, false, &session.progress);
+            let missing = store.apply(&game_id, name, &mut mod_pack).ok_or_else(|| format!("No playset named {}",name))?;
+            for mod_file in &missing {
+                session.progress.warn(ProgressStage::Scanning, &format!("Mod in playset is no longer installed: {}",mod_file.display()));
+            }
+            set_entire_mod_list(&config.mod_path, config.new_launcher, &mod_pack.load_order(), &session.progress)?;
+            if session.text() {
+                println!("Loaded playset {}",name);
+            }
+            Ok(json!({ "game": game_id, "playset": name, "mods": store.get(&game_id, name), "missing": missing }))
+        },
+        ("delete",Some(name)) => {
+            if !store.remove(&game_id, name) {
+                return Err(format!("No playset named {}",name).into());
+            }
+            store.save(&store_path)?;
+            if session.text() {
+                println!("Deleted playset {}",name);
+            }
+            Ok(json!({ "game": game_id, "playset": name }))
+        },
+        _ => unreachable!("clap only accepts known playset commands"),
+    }
+}
+
+fn run_config(session: &Session, sub: &ArgMatches) -> CommandResult {
+    let config_path = session.matches.get_one::<PathBuf>("config").cloned().unwrap_or_else(user_config_path);
+    match sub.subcommand() {
+        Some(("init", init)) => {
+            if session.matches.get_one::<PathBuf>("config").is_some() {
+                return Err("config init always writes the user's merger.toml, leave out --config".into());
+            }
+            if config_path.exists() && !init.get_flag("force") {
+                return Err(format!("{} already exists, pass --force to replace it",config_path.display()).into());
+            }
+            let configs = generate_default_configs();
+            store_user_configs(&configs)?;
+            if session.text() {
+                println!("Wrote default configuration to {}",config_path.display());
+            }
+            let games: Vec<&str> = configs.iter().map(|config| config.game_name.as_str()).collect();
+            Ok(json!({ "path": config_path, "games": games }))
+        },
+        Some(("show", _)) => {
+            let contents = std::fs::read_to_string(&config_path)
+                .map_err(|e| format!("Could not read {}: {}",config_path.display(),e))?;
+            if session.text() {
+                print!("{}",contents);
+            }
+            Ok(json!({ "path": config_path, "contents": contents }))
+        },
+        Some(("edit", _)) => {
+            let editor = std::env::var("VISUAL").or_else(|_| std::env::var("EDITOR"))
+                .unwrap_or_else(|_| if cfg!(windows) { "notepad".to_owned() } else { "vi".to_owned() });
+            let status = std::process::Command::new(&editor).arg(&config_path).status()?;
+            if !status.success() {
+                return Err(format!("{} exited with {}",editor,status).into());
+            }
+            Ok(json!({ "path": config_path }))
+        },
+        _ => unreachable!("clap only accepts known config commands"),
+    }
+}
+
+fn run_report(session: &Session, sub: &ArgMatches) -> CommandResult {
+    let report_path = sub.get_one::<PathBuf>("report_file").ok_or("A report file is required")?;
+    let report = MergeReport::from_json(&std::fs::read_to_string(report_path)?)?;
+    let html_path = sub.get_one::<PathBuf>("html");
+    if let Some(html_path) = html_path {
+        std::fs::write(html_path, report.to_html())?;
+        eprintln!("HTML report written to {}",html_path.display());
+    } else if session.text() {
+        println!("{}: {} of {} conflicting files merged successfully",report.patch_name,report.successful(),report.conflicts.len());
+        for conflict in &report.conflicts {
+            println!("{:?}\t{}\t{}",conflict.outcome,conflict.path.display(),conflict.mods.join(", "));
+        }
+    }
+    Ok(json!({ "report": report, "html": html_path }))
+}
+
+/// Score of the automatic merge with one combination of settings
+#[derive(Serialize)]
+struct BenchmarkResult {
+    options: DiffOptions,
+    accuracy: f64,
+    score: BenchmarkScore,
+}
+
+fn run_benchmark(session: &Session, sub: &ArgMatches) -> CommandResult {
+    let corpus = sub.get_one::<PathBuf>("corpus").ok_or("A corpus folder is required")?;
+    let base = match sub.get_one::<PathBuf>("path") {
+        Some(path) => load_config(session, sub)?.0.diff_options_for(path),
+        None => DiffOptions::default(),
+    };
+    let fixtures = MergeFixture::open_corpus(corpus)?;
+    if fixtures.is_empty() {
+        return Err(format!("No fixtures were found in {}",corpus.display()).into());
+    }
+
+    let mut results = Vec::new();
+    for max_fuzz in sub_values(sub, "max-fuzz", base.max_fuzz) {
+        for context_lines in sub_values(sub, "context-lines", base.context_lines) {
+            for ignore_comments in sub_values(sub, "ignore-comments", base.ignore_comments) {
+                for ignore_indentation in sub_values(sub, "ignore-indentation", base.ignore_indentation) {
+                    let options = DiffOptions {max_fuzz, context_lines, ignore_comments, ignore_indentation};
+                    let score = score_fixtures(&fixtures, &options);
+                    results.push(BenchmarkResult {options, accuracy: score.accuracy(), score});
+                }
+            }
+        }
+    }
+    // Best first, ties keeping the order they were tried in
+    results.sort_by(|a,b| b.accuracy.partial_cmp(&a.accuracy).unwrap_or(std::cmp::Ordering::Equal));
+
+    if session.text() {
+        println!("{:>8}  {:>4} {:>7} {:>8} {:>6}  {:>10} {:>12} {:>11} {:>13}","accuracy","fuzz","context","comments","indent","merged ok","merged wrong","refused ok","refused wrong");
+        for result in &results {
+            let DiffOptions {max_fuzz,context_lines,ignore_comments,ignore_indentation} = result.options;
+            let score = &result.score;
+            println!("{:>7.1}%  {:>4} {:>7} {:>8} {:>6}  {:>10} {:>12} {:>11} {:>13}",result.accuracy * 100.0,max_fuzz,context_lines,
+                if ignore_comments { "ignored" } else { "kept" },if ignore_indentation { "ignored" } else { "kept" },
+                score.merged_correctly,score.merged_wrongly,score.refused_correctly,score.refused_wrongly);
+        }
+        if let Some(best) = results.first().filter(|best| !best.score.failures.is_empty()) {
+            println!("The best settings got these fixtures wrong: {}",best.score.failures.join(", "));
+        }
+    }
+    Ok(json!({ "fixtures": fixtures.len(), "results": results }))
+}
+
+// Every value given for a setting, or the starting value if none were
+fn sub_values<T: Clone + Send + Sync + 'static>(sub: &ArgMatches, id: &str, start: T) -> Vec<T> {
+    match sub.get_many::<T>(id) {
+        Some(values) => values.cloned().collect(),
+        None => vec![start],
+    }
+}
+
+/// Writes the merge report as a standalone HTML page if the path ends in .html or .htm, or as JSON otherwise
+fn write_report(report: &MergeReport, report_path: &Path) -> CliResult {
+    let is_html = match report_path.extension().and_then(|ext| ext.to_str()) {
+        Some(ext) => ext.eq_ignore_ascii_case("html") || ext.eq_ignore_ascii_case("htm"),
+        None => false,
+    };
+    let contents = if is_html { report.to_html() } else { report.to_json()? };
+    std::fs::write(report_path, contents)?;
+    Ok(())
+}
+
+/// Prints which files would merge cleanly and which would need merging by hand, followed by the diff of each clean merge
+fn print_preview(report: &MergeReport) {
+    if report.conflicts.is_empty() {
+        println!("No mod conflicts were found");
+        return;
+    }
+    for conflict in &report.conflicts {
+        let verdict = match conflict.outcome {
+            MergeOutcome::Overridden => "override",
+            outcome if outcome.is_success() => "clean",
+            _ => "conflict",
+        };
+        println!("{:<9}{}\t{}",verdict,conflict.path.display(),conflict.mods.join(", "));
+    }
+    println!("{} of {} conflicting files would merge cleanly",report.successful(),report.conflicts.len());
+    for diff in report.conflicts.iter().filter_map(|conflict| conflict.diff.as_ref()) {
+        println!();
+        print!("{}",diff);
+    }
+}
+
+/// Lists the overrides to revisit because a mod or the game changed the file since they were written
+fn print_stale_overrides(report: &MergeReport) {
+    let stale = report.stale_overrides();
+    if !stale.is_empty() {
+        println!("{} overrides were written against older inputs and should be checked:",stale.len());
+        for item in stale {
+            println!("    {}\t{}",item.path.display(),item.changed.join(", "));
+        }
+    }
+}
+
+/// Prints every pair of conflicting mods, marking pairs that only clash in unmergeable folders
+fn print_matrix(matrix: &ConflictMatrix) {
+    for pair in &matrix.pairs {
+        let marker = if pair.all_unmergeable { " [unmergeable]" } else { "" };
+        println!("{} <-> {}: {} shared files{}",pair.first,pair.second,pair.files.len(),marker);
+        for file in &pair.files {
+            println!("    {}",file.display());
+        }
+    }
+}
+
+/// Writes the conflict matrix as CSV if the path ends in .csv, or as JSON otherwise
+fn write_matrix(matrix: &ConflictMatrix, matrix_path: &Path) -> CliResult {
+    let is_csv = match matrix_path.extension().and_then(|ext| ext.to_str()) {
+        Some(ext) => ext.eq_ignore_ascii_case("csv"),
+        None => false,
+    };
+    let contents = if is_csv { matrix.to_csv() } else { matrix.to_json()? };
+    std::fs::write(matrix_path, contents)?;
+    Ok(())
+}
@@ -0,0 +1,135 @@
+// Walks through the hunks that neither the automatic merge nor the saved resolutions could settle
+use std::io::{BufRead,Write};
+
+use paradoxmerger::{ConflictHunk,Resolution,ResolutionChoice,UnresolvedConflict};
+
+const SEPARATOR: &str = " | ";
+const MIN_COLUMN_WIDTH: usize = 12;
+
+/// What was decided for a single hunk
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub enum Decision {
+    Resolve(Resolution),
+    /// Leave the hunk for a later run
+    Skip,
+    /// Stop resolving, keeping the choices made so far
+    Quit,
+}
+
+/// Shows conflicting hunks side by side and asks which version to keep
+pub struct Resolver<R,W> {
+    input: R,
+    output: W,
+    width: usize,
+}
+
+impl<R: BufRead, W: Write> Resolver<R,W> {
+    /// Creates a resolver drawing columns to fit a terminal `width` characters wide
+    pub fn new(input: R, output: W, width: usize) -> Self {
+        Resolver {input, output, width}
+    }
+
+    /// Prints the vanilla lines next to every mod's version of a hunk, then asks how to resolve it
+    /// Running out of input counts as quitting
+    pub fn ask(&mut self, conflict: &UnresolvedConflict, hunk: &ConflictHunk, number: usize, total: usize) -> std::io::Result<Decision> {
+        let changed: Vec<(&str,&Vec<String>)> = conflict.mods.iter().zip(&hunk.versions)
+            .filter_map(|(name,version)| version.as_ref().map(|lines| (name.as_str(),lines)))
+            .collect();
+
+        writeln!(self.output)?;
+        writeln!(self.output, "Hunk {} of {}: {} at line {}", number, total, conflict.path.display(), hunk.base_start + 1)?;
+        let mut columns = vec![("Vanilla".to_owned(), &hunk.base)];
+        columns.extend(changed.iter().enumerate().map(|(i,(name,lines))| (format!("[{}] {}",i + 1,name), *lines)));
+        write!(self.output, "{}", side_by_side(&columns, self.width))?;
+
+        loop {
+            write!(self.output, "Keep [v]anilla, a mod [1-{}], [b]oth in load order, [e]dit, [s]kip or [q]uit? ", changed.len())?;
+            self.output.flush()?;
+            let answer = match self.read_line()? {
+                Some(answer) => answer,
+                None => return Ok(Decision::Quit),
+            };
+            let resolution = match answer.trim() {
+                "v" => Resolution {choice: ResolutionChoice::Vanilla, lines: hunk.base.clone(), policy: None},
+                "b" => Resolution {choice: ResolutionChoice::AllInOrder, lines: hunk.all_in_order(), policy: None},
+                "e" => match self.read_edit()? {
+                    Some(lines) => Resolution {choice: ResolutionChoice::Edited, lines, policy: None},
+                    None => return Ok(Decision::Quit),
+                },
+                "s" => return Ok(Decision::Skip),
+                "q" => return Ok(Decision::Quit),
+                other => match other.parse::<usize>().ok().and_then(|i| i.checked_sub(1)).and_then(|i| changed.get(i)) {
+                    Some((name,lines)) => Resolution {choice: ResolutionChoice::Mod((*name).to_owned()), lines: lines.to_vec(), policy: None},
+                    None => continue,
+                },
+            };
+            return Ok(Decision::Resolve(resolution));
+        }
+    }
+
+    // Reads replacement lines up to a line holding a single full stop
+    fn read_edit(&mut self) -> std::io::Result<Option<Vec<String>>> {
+        writeln!(self.output, "Type the lines to use instead, then a line with just a full stop:")?;
+        self.output.flush()?;
+        let mut lines = Vec::new();
+        loop {
+            match self.read_line()? {
+                Some(line) if line == "." => return Ok(Some(lines)),
+                Some(line) => lines.push(line),
+                None => return Ok(None),
+            }
+        }
+    }
+
+    fn read_line(&mut self) -> std::io::Result<Option<String>> {
+        let mut line = String::new();
+        if self.input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let trimmed = line.trim_end_matches(['\r','\n']).len();
+        line.truncate(trimmed);
+        Ok(Some(line))
+    }
+}
+
+/// Width of the terminal from `$COLUMNS`, or a common default if it is not set
+pub fn terminal_width() -> usize {
+    std::env::var("COLUMNS").ok().and_then(|columns| columns.parse().ok()).unwrap_or(120)
+}
+
+// Lays out titled columns of lines next to each other, cutting off lines too long for their column
+fn side_by_side(columns: &[(String,&Vec<String>)], width: usize) -> String {
+    let separators = SEPARATOR.len() * columns.len().saturating_sub(1);
+    let column_width = (width.saturating_sub(separators) / columns.len().max(1)).max(MIN_COLUMN_WIDTH);
+    let rows = columns.iter().map(|(_,lines)| lines.len()).max().unwrap_or(0);
+
+    let mut text = String::new();
+    let titles: Vec<&str> = columns.iter().map(|(title,_)| title.as_str()).collect();
+    push_row(&mut text, &titles, column_width);
+    let rule = "-".repeat(column_width);
+    push_row(&mut text, &vec![rule.as_str(); columns.len()], column_width);
+    for row in 0..rows {
+        let cells: Vec<&str> = columns.iter().map(|(_,lines)| lines.get(row).map(|line| line.as_str()).unwrap_or("")).collect();
+        push_row(&mut text, &cells, column_width);
+    }
+    text
+}
+
+fn push_row(text: &mut String, cells: &[&str], column_width: usize) {
+    let cells: Vec<String> = cells.iter().map(|cell| fit(cell, column_width)).collect();
+    text.push_str(cells.join(SEPARATOR).trim_end());
+    text.push('\n');
+}
+
+// Pads or cuts a cell to exactly the column width, marking cut lines with a tilde
+fn fit(cell: &str, column_width: usize) -> String {
+    let cell = cell.replace('\t', "    ");
+    let length = cell.chars().count();
+    if length > column_width {
+        let mut cut: String = cell.chars().take(column_width - 1).collect();
+        cut.push('~');
+        cut
+    } else {
+        format!("{}{}", cell, " ".repeat(column_width - length))
+    }
+}
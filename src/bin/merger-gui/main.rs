@@ -3,15 +3,19 @@ mod vgtk_ext;
 
 use vgtk::ext::*;
 use vgtk::lib::gio::ApplicationFlags;
+use vgtk::lib::gio::prelude::ApplicationExtManual;
 use vgtk::lib::gtk::*;
-use vgtk::{gtk, run, Component, UpdateAction, VNode};
+use vgtk::{gtk, start, Component, Scope, UpdateAction, VNode};
 use std::path::{PathBuf,Path};
 
 use vgtk_ext::*;
 use std::env;
+use std::sync::Mutex;
+use std::thread;
 
-use paradoxmerger::configs::{ConfigOptions,fetch_user_configs,ArgOptions};
-use paradoxmerger::{generate_entire_mod_list,ModPack,ModStatus,ModToken,auto_merge,extract_all_files};
+use paradoxmerger::configs::{ConfigOptions,fetch_user_configs,ArgOptions,PatchFormat};
+use paradoxmerger::{generate_entire_mod_list,ModPack,ModReader,ModStatus,ModToken,generate_patch,restore_patch_backup};
+use paradoxmerger::{ProgressEvent,ProgressReporter};
 
 const H_PADDING: i32 = 10;
 const V_PADDING: i32 = 20;
@@ -34,6 +38,50 @@ impl Renderable for ModStatus {
     }
 }
 
+/// Collects progress events as lines for the log pane
+#[derive(Default)]
+struct LogProgress {
+    lines: Mutex<Vec<String>>,
+}
+
+impl LogProgress {
+    fn into_lines(self) -> Vec<String> {
+        self.lines.into_inner().unwrap_or_default()
+    }
+}
+
+impl ProgressReporter for LogProgress {
+    fn report(&self, event: ProgressEvent) {
+        if let (Some(line),Ok(mut lines)) = (log_line(event),self.lines.lock()) {
+            lines.push(line);
+        }
+    }
+}
+
+/// Sends progress events to the window as log lines, so a patch generated on a worker thread shows up while it runs
+struct ScopeProgress {
+    scope: Scope<Model>,
+}
+
+impl ProgressReporter for ScopeProgress {
+    fn report(&self, event: ProgressEvent) {
+        if let Some(line) = log_line(event) {
+            // The window may have been closed while merging, then there is no one left to tell
+            let _ = self.scope.try_send(Message::MergeLog(line));
+        }
+    }
+}
+
+fn log_line(event: ProgressEvent) -> Option<String> {
+    match event {
+        ProgressEvent::Started { stage, total } => Some(format!("{:?}: {} items",stage,total)),
+        ProgressEvent::Processed { .. } => None,
+        ProgressEvent::Conflict { path, mods } => Some(format!("Merging {} from {}",path.display(),mods.join(", "))),
+        ProgressEvent::Warning { message, .. } => Some(format!("Warning: {}",message)),
+        ProgressEvent::Finished { stage } => Some(format!("{:?}: done",stage)),
+    }
+}
+
 #[derive(Clone, Debug)]
 struct Model {
     configs: Vec<ConfigOptions>,
@@ -41,8 +89,13 @@ struct Model {
     config_selected: Option<String>,
     output_path: PathBuf,
     extract_all: bool,
+    zip_patch: bool,
+    incremental: bool,
     scan_auto: bool,
     patch_name: String,
+    log: Vec<String>,
+    scope: Option<Scope<Model>>,
+    merging: bool,
 }
 
 impl Default for Model {
@@ -53,8 +106,13 @@ impl Default for Model {
             config_selected: None,
             output_path: env::current_dir().unwrap_or_default(),
             extract_all: false,
+            zip_patch: false,
+            incremental: false,
             scan_auto: false,
             patch_name: String::from("Merged Patch"),
+            log: Vec::new(),
+            scope: None,
+            merging: false,
         }
     }
 }
@@ -75,12 +133,19 @@ enum Message {
     ConfigSelected(Option<String>),
     ToggleScan,
     ToggleExtract,
+    ToggleZip,
+    ToggleIncremental,
     ManualScan,
     SetPatchName(String),
     SetOutputPath(String),
+    UseGameModDir,
     SaveLoadOrder,
     GeneratePatch,
+    RestoreBackup,
     ToggleModStatus(ModToken),
+    Connect(Scope<Model>),
+    MergeLog(String),
+    MergeFinished(Result<(),String>),
 }
 
 async fn print_ok_dialog() -> vgtk::lib::gtk::ResponseType {
@@ -99,10 +164,12 @@ impl Component for Model {
             },
             Message::ConfigSelected(s) => {
                 self.config_selected = s.clone();
+                let progress = LogProgress::default();
                 self.mod_pack = match s {
-                    Some(text) => update_mod_pack(text, self.scan_auto, &self.configs),
+                    Some(text) => update_mod_pack(text, self.scan_auto, &self.configs, &progress),
                     None => ModPack::default(),
                 };
+                self.log = progress.into_lines();
                 UpdateAction::Render
             },
             Message::ToggleScan => {
@@ -113,15 +180,25 @@ impl Component for Model {
                 self.extract_all = !self.extract_all;
                 UpdateAction::None
             },
+            Message::ToggleZip => {
+                self.zip_patch = !self.zip_patch;
+                UpdateAction::None
+            },
+            Message::ToggleIncremental => {
+                self.incremental = !self.incremental;
+                UpdateAction::None
+            },
             Message::ManualScan => {
+                let progress = LogProgress::default();
                 if let Some(config) = &self.get_current_config() {
                 let vanilla = paradoxmerger::files_in_vanilla(&config);
                 let val_ref: Vec<&Path> = vanilla.iter().map(|x| x.as_path()).collect();
                 self.mod_pack.register_vanilla(&val_ref);
             
-                self.mod_pack.generate_conflicts();
+                self.mod_pack.generate_conflicts(&progress);
                 }
-                UpdateAction::None
+                self.log = progress.into_lines();
+                UpdateAction::Render
             },
             Message::SetPatchName(patch_name) => {
                 self.patch_name = patch_name.clone();
@@ -131,30 +208,80 @@ impl Component for Model {
                 self.output_path = PathBuf::from(output_path);
                 UpdateAction::None
             },
+            Message::UseGameModDir => {
+                if let Some(config) = self.get_current_config() {
+                    self.output_path = config.game_mod_dir();
+                }
+                UpdateAction::Render
+            },
             Message::SaveLoadOrder => {
                 if let Some(config) = &self.get_current_config() { 
                     let load_order = self.mod_pack.load_order();
-                    let _res = paradoxmerger::set_entire_mod_list(&config.mod_path, config.new_launcher,&load_order);
+                    let progress = LogProgress::default();
+                    let saved = paradoxmerger::set_entire_mod_list(&config.mod_path, config.new_launcher, &load_order, &progress);
+                    self.log = progress.into_lines();
+                    if let Err(e) = saved {
+                        self.log.push(format!("Could not save the load order: {}",e));
+                    }
                 }
-                UpdateAction::None
+                UpdateAction::Render
             },
             Message::GeneratePatch => {
-                if let Some(conf_name) = &self.config_selected {
-                    let conf = self.configs.iter().find(|c| &c.game_name == conf_name);
-                    let conf = match conf {Some(c) => c, _ => return UpdateAction::None};
-
-                    let args = ArgOptions::new(PathBuf::new(), self.extract_all, false, true, String::new(), self.patch_name.clone());
+                // Only one patch at a time, the button stays disabled until the worker is done
+                let scope = match (&self.scope,self.merging) {
+                    (Some(scope),false) => scope.clone(),
+                    _ => return UpdateAction::None,
+                };
+                if let Some(conf) = self.get_current_config().cloned() {
+                    let patch_format = if self.zip_patch { PatchFormat::Zip } else { PatchFormat::Folder };
+                    let args = ArgOptions::new(PathBuf::new(), self.extract_all, false, true, String::new(), self.patch_name.clone())
+                        .with_patch_format(patch_format)
+                        .with_output_dir(self.output_path.clone())
+                        .with_incremental(self.incremental);
+                    let mod_pack = self.mod_pack.clone();
+                    self.log.clear();
+                    self.merging = true;
 
-                    if self.extract_all {
-                        extract_all_files(&self.mod_pack, &args, &conf, false, &self.output_path);
-                    }
-                    let merge_result = auto_merge(conf,&args,&self.mod_pack);
-                    
-                    
-                    let _res2 = vgtk::run_dialog::<MergeDialog>(vgtk::current_window().as_ref());
+                    // Merging can take minutes, so it runs off the UI thread and reports back through the scope
+                    thread::spawn(move || {
+                        let progress = ScopeProgress { scope };
+                        let reader = ModReader::new();
+                        let merge_result = generate_patch(&conf,&args,&mod_pack,&reader,&progress)
+                            .map(|_| ())
+                            .map_err(|e| e.to_string());
+                        let _ = progress.scope.try_send(Message::MergeFinished(merge_result));
+                    });
+                }
+                UpdateAction::Render
+            },
+            Message::MergeLog(line) => {
+                self.log.push(line);
+                UpdateAction::Render
+            },
+            Message::MergeFinished(merge_result) => {
+                self.merging = false;
+                if let Err(e) = merge_result {
+                    self.log.push(format!("Patch generation failed: {}",e));
                 }
+
+                let _res2 = vgtk::run_dialog::<MergeDialog>(vgtk::current_window().as_ref());
+                UpdateAction::Render
+            },
+            Message::Connect(scope) => {
+                self.scope = Some(scope);
                 UpdateAction::None
             },
+            Message::RestoreBackup => {
+                let args = ArgOptions::new(PathBuf::new(), false, false, true, String::new(), self.patch_name.clone())
+                    .with_output_dir(self.output_path.clone());
+                let line = match restore_patch_backup(&args) {
+                    Ok(true) => format!("Restored the previous version of {}",args.folder_name()),
+                    Ok(false) => format!("There is no backup of {} to restore",args.folder_name()),
+                    Err(e) => format!("Error restoring backup: {}",e),
+                };
+                self.log.push(line);
+                UpdateAction::Render
+            },
             Message::ToggleModStatus(token) => {
                 match self.mod_pack.toggle_by_token(token) {
                     Some(_) => (),
@@ -189,12 +316,17 @@ impl Component for Model {
                 <Box spacing=H_PADDING>
                     <Label label="Output Directory:".to_owned() />
                     <Entry property_width_request=300 text=self.output_path.to_string_lossy().as_ref().clone() on changed=|a| Message::SetOutputPath(gstring_to_string(a.get_text())) />
+                    <Button label="Game Mod Folder".to_owned() tooltip_text="Write the patch where the launcher will find it.".to_owned() on clicked=|_| Message::UseGameModDir />
                 </Box>
                 <Box spacing=H_PADDING>
                     <Label label="Extract all files: "/>
                     <CheckButton on toggled=|_| Message::ToggleExtract />
                     <Label label="Scan Automatically (SLOW): "/>
                     <CheckButton on toggled=|_| Message::ToggleScan />
+                    <Label label="Package as zip: "/>
+                    <CheckButton on toggled=|_| Message::ToggleZip />
+                    <Label label="Reuse unchanged merges: "/>
+                    <CheckButton on toggled=|_| Message::ToggleIncremental />
                 </Box>
                 <Box spacing=H_PADDING>
                     <Label label="Output Mod Title:"/>
@@ -203,8 +335,18 @@ impl Component for Model {
                 <Box spacing=H_PADDING>
                     <Button label="Save Load Order".to_owned() on clicked=|_| Message::SaveLoadOrder />
                     <Button label="Scan Mod Conflicts".to_owned() on clicked=|_| Message::ManualScan />
-                    <Button label="Generate Patch".to_owned() on clicked=|_| Message::GeneratePatch />
+                    <Button label="Generate Patch".to_owned() sensitive=!self.merging on clicked=|_| Message::GeneratePatch />
+                    <Button label="Restore Backup".to_owned() on clicked=|_| Message::RestoreBackup />
                 </Box>
+                <Frame property_height_request=200 Box::expand=true>
+                <ScrolledWindow>
+                <ListBox>
+                {
+                    self.log.iter().map(|line| gtk! { <ListBoxRow halign=Align::Start><Label label=line.clone() /></ListBoxRow> })
+                }
+                </ListBox>
+                </ScrolledWindow>
+                </Frame>
                 </Box>
                 </Box>
                 </Window>
@@ -221,10 +363,10 @@ fn list_config_entries(configs: &[ConfigOptions]) -> Vec<(Option<String>,String)
     vec
 }
 
-fn update_mod_pack(selected_idx: String, register_conflicts: bool, configs: &[ConfigOptions]) -> ModPack {
+fn update_mod_pack(selected_idx: String, register_conflicts: bool, configs: &[ConfigOptions], progress: &dyn ProgressReporter) -> ModPack {
     let conf: Option<&ConfigOptions> = configs.iter().find(|m| m.game_name == selected_idx);
     if let Some(config) = conf {
-        let mod_list = generate_entire_mod_list(&config.mod_path, config.new_launcher);
+        let mod_list = generate_entire_mod_list(&config.mod_path, config.new_launcher, progress);
         let mut new_pack = ModPack::default()
             .restrict_paths(&config.valid_paths)
             .restrict_extensions(&config.valid_extensions);
@@ -234,9 +376,9 @@ fn update_mod_pack(selected_idx: String, register_conflicts: bool, configs: &[Co
             let val_ref: Vec<&Path> = vanilla.iter().map(|x| x.as_path()).collect();
             new_pack.register_vanilla(&val_ref);
             
-            new_pack.add_mods(&mod_list, true, true);
+            new_pack.add_mods(&mod_list, true, true, progress);
         } else {
-            new_pack.add_mods(&mod_list, false, false);
+            new_pack.add_mods(&mod_list, false, false, progress);
         }
         
         new_pack
@@ -281,5 +423,9 @@ impl Component for MergeDialog {
 
 fn main() {
     pretty_env_logger::init();
-    std::process::exit(run::<Model>());
+    let (app, scope) = start::<Model>();
+    // The worker thread generating a patch needs a way to send messages back to the window
+    scope.send_message(Message::Connect(scope.clone()));
+    let args: Vec<String> = env::args().collect();
+    std::process::exit(app.run(&args));
 }
\ No newline at end of file
@@ -1,10 +1,12 @@
 
 use crate::io::{files,re};
+use crate::io::encodings::{LineEnding,TextEncoding};
+use crate::merge_diff::{DiffOptions,DiffSettings};
 use directories::{ProjectDirs,BaseDirs};
 
 use std::fs::{self,File};
 use std::io::{prelude::*};
-use std::collections::HashMap;
+use std::collections::{BTreeMap,HashMap};
 use std::path::{Path,PathBuf};
 use serde::{Deserialize,Serialize};
 
@@ -30,6 +32,16 @@ impl SupportedGame {
     }
 }
 
+/// How the generated patch is laid out on disk
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum PatchFormat {
+    /// Loose files in a folder, referenced with `path =` in the descriptor
+    Folder,
+    /// A single zip file, referenced with `archive =` in the descriptor
+    Zip,
+}
+
+#[derive(Clone,Debug)]
 pub struct ArgOptions {
     pub config_path: PathBuf,
     pub extract: bool,
@@ -37,12 +49,82 @@ pub struct ArgOptions {
     pub verbose: bool,
     pub game_id: String,
     pub patch_name: String,
+    pub jobs: usize,
+    pub patch_format: PatchFormat,
+    pub output_dir: PathBuf,
+    pub incremental: bool,
+    pub review_patches: bool,
+    pub resolutions: Option<PathBuf>,
 }
 
 impl ArgOptions {
     pub fn new(config_path: PathBuf, extract: bool, dry_run: bool, verbose: bool, game_id: String, patch_name: String) -> Self {
-        ArgOptions {config_path,extract,dry_run,verbose,game_id,patch_name}
+        ArgOptions {config_path,extract,dry_run,verbose,game_id,patch_name,jobs: 0,patch_format: PatchFormat::Folder,output_dir: PathBuf::new(),incremental: false,review_patches: false,resolutions: None}
+    }
+
+    /// Sets the number of worker threads used for merging, zero uses one per available core
+    pub fn with_jobs(self, jobs: usize) -> Self {
+        let mut new_args = self;
+        new_args.jobs = jobs;
+        new_args
+    }
+
+    /// Sets whether the generated patch is written as a folder or a zip file
+    pub fn with_patch_format(self, patch_format: PatchFormat) -> Self {
+        let mut new_args = self;
+        new_args.patch_format = patch_format;
+        new_args
+    }
+
+    /// Sets the directory the patch and its descriptor are written into, such as the game's mod directory
+    pub fn with_output_dir(self, output_dir: PathBuf) -> Self {
+        let mut new_args = self;
+        new_args.output_dir = output_dir;
+        new_args
+    }
+
+    /// Sets whether conflicts whose inputs are unchanged since the last run reuse their previous output
+    pub fn with_incremental(self, incremental: bool) -> Self {
+        let mut new_args = self;
+        new_args.incremental = incremental;
+        new_args
+    }
+
+    /// Sets whether a patch file is written for every merged conflict, showing the output against vanilla and each mod
+    pub fn with_review_patches(self, review_patches: bool) -> Self {
+        let mut new_args = self;
+        new_args.review_patches = review_patches;
+        new_args
     }
+
+    /// Sets the file of saved resolutions used to settle hunks the automatic merge could not, None to leave them for manual merging
+    pub fn with_resolutions(self, resolutions: Option<PathBuf>) -> Self {
+        let mut new_args = self;
+        new_args.resolutions = resolutions;
+        new_args
+    }
+
+    /// The directory the patch and its descriptor are written into
+    /// Relative paths, including an empty one, are resolved against the current directory
+    pub fn output_root(&self) -> Result<PathBuf,std::io::Error> {
+        let current_dir = std::env::current_dir()?;
+        Ok(files::join_unless_absolute(current_dir, self.output_dir.clone()))
+    }
+
+    /// Names of every file and folder in the output directory that belong to this patch
+    pub fn patch_entries(&self) -> Vec<String> {
+        let name = self.folder_name();
+        vec![format!("{}.mod",name), format!("{}.zip",name), format!("{}_bad",name), format!("{}_patches",name), name]
+    }
+
+    /// The path of the patch relative to the game's user directory, as written in the descriptor
+    pub fn descriptor_entry(&self) -> String {
+        match self.patch_format {
+            PatchFormat::Folder => format!("path = \"mod/{}\"", self.folder_name()),
+            PatchFormat::Zip => format!("archive = \"mod/{}.zip\"", self.folder_name()),
+        }
+    }
+
     pub fn folder_name(&self) -> String {
         let mut mod_folder = self.patch_name.clone();
         mod_folder.make_ascii_lowercase();
@@ -59,6 +141,28 @@ pub struct ConfigOptions {
     pub valid_extensions: Vec<String>,
     pub no_transcode: Vec<String>,
     pub new_launcher: bool,
+    /// Folders whose files can never be merged, only replaced
+    #[serde(default)]
+    pub unmergeable_paths: Vec<PathBuf>,
+    /// Folder of hand-written files, laid out like a mod, that replace the merged output for the same paths
+    #[serde(default)]
+    pub override_dir: Option<PathBuf>,
+    /// Encoding the game expects for the files in a folder, the most specific folder winning
+    /// Files in other folders are written in the encoding of their vanilla version
+    #[serde(default)]
+    pub encodings: BTreeMap<PathBuf,TextEncoding>,
+    /// Line ending merged files are written with, None to use that of their vanilla version
+    #[serde(default)]
+    pub line_ending: Option<LineEnding>,
+    /// Settings of the automatic merge for every file of the game
+    #[serde(default)]
+    pub diff_options: DiffOptions,
+    /// Settings of the automatic merge for the files in a folder, the most specific folder winning over `diff_options`
+    #[serde(default)]
+    pub path_diff_options: BTreeMap<PathBuf,DiffOptions>,
+    /// Settings of the automatic merge found in the config that no longer change anything, warned about when merging
+    #[serde(skip)]
+    pub ignored_settings: Vec<String>,
 }
 
 #[derive(Deserialize,Serialize,Debug)]
@@ -69,6 +173,18 @@ struct ConfigListItem {
     valid_extensions: Vec<String>,
     no_transcode: Vec<String>,
     new_launcher: bool,
+    #[serde(default)]
+    unmergeable_paths: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    override_dir: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    encodings: BTreeMap<String,TextEncoding>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    line_ending: Option<LineEnding>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    diff_options: Option<DiffSettings>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    path_diff_options: BTreeMap<String,DiffSettings>,
 }
 
 type TomlConfigItem = (String,ConfigListItem);
@@ -77,6 +193,13 @@ impl From<TomlConfigItem> for ConfigOptions {
     fn from((game_id, config_info): TomlConfigItem) -> Self {
         let valid_paths: Vec<PathBuf> = config_info.valid_paths.iter().map(|x| PathBuf::from(&x)).collect();
         let valid_extensions: Vec<String> = config_info.valid_extensions;
+        let mut ignored_settings: Vec<String> = config_info.diff_options.iter()
+            .flat_map(|settings| settings.ignored())
+            .map(|name| format!("diff_options.{}",name))
+            .collect();
+        for (folder,settings) in &config_info.path_diff_options {
+            ignored_settings.extend(settings.ignored().into_iter().map(|name| format!("path_diff_options.\"{}\".{}",folder,name)));
+        }
         ConfigOptions {
             game_name: game_id,
             mod_path: PathBuf::from(config_info.modpath),
@@ -85,6 +208,13 @@ impl From<TomlConfigItem> for ConfigOptions {
             valid_extensions,
             no_transcode: config_info.no_transcode,
             new_launcher: config_info.new_launcher,
+            unmergeable_paths: config_info.unmergeable_paths.iter().map(PathBuf::from).collect(),
+            override_dir: config_info.override_dir.map(PathBuf::from),
+            encodings: config_info.encodings.into_iter().map(|(folder,encoding)| (PathBuf::from(folder),encoding)).collect(),
+            line_ending: config_info.line_ending,
+            diff_options: config_info.diff_options.map(DiffOptions::from).unwrap_or_default(),
+            path_diff_options: config_info.path_diff_options.into_iter().map(|(folder,settings)| (PathBuf::from(folder),DiffOptions::from(settings))).collect(),
+            ignored_settings,
         }
     }
 }
@@ -99,6 +229,12 @@ impl From<&ConfigOptions> for TomlConfigItem {
             valid_extensions: config_option.valid_extensions.clone(),
             no_transcode: config_option.no_transcode.clone(),
             new_launcher: config_option.new_launcher,
+            unmergeable_paths: config_option.unmergeable_paths.iter().map(|x| x.to_string_lossy().to_string()).collect(),
+            override_dir: config_option.override_dir.as_ref().map(|x| x.to_string_lossy().to_string()),
+            encodings: config_option.encodings.iter().map(|(folder,encoding)| (folder.to_string_lossy().to_string(),*encoding)).collect(),
+            line_ending: config_option.line_ending,
+            diff_options: Some(config_option.diff_options).filter(|options| *options != DiffOptions::default()).map(DiffSettings::from),
+            path_diff_options: config_option.path_diff_options.iter().map(|(folder,options)| (folder.to_string_lossy().to_string(),DiffSettings::from(*options))).collect(),
         };
         (config_option.game_name.clone(),config_list_item)
     }
@@ -106,7 +242,7 @@ impl From<&ConfigOptions> for TomlConfigItem {
 
 impl ConfigOptions {
     pub fn new(game_name: String, mod_path: PathBuf, data_path: PathBuf, valid_paths: &[PathBuf], valid_extensions: &[String], no_transcode: &[String], new_launcher: bool) -> Self {
-        ConfigOptions {game_name,mod_path,data_path,valid_paths: valid_paths.to_vec(), valid_extensions: valid_extensions.to_vec(),no_transcode: no_transcode.to_vec(),new_launcher}
+        ConfigOptions {game_name,mod_path,data_path,valid_paths: valid_paths.to_vec(), valid_extensions: valid_extensions.to_vec(),no_transcode: no_transcode.to_vec(),new_launcher,unmergeable_paths: Vec::new(),override_dir: None,encodings: BTreeMap::new(),line_ending: None,diff_options: DiffOptions::default(),path_diff_options: BTreeMap::new(),ignored_settings: Vec::new()}
     }
 
     pub fn new_with_str(game_name: String, mod_path: PathBuf, data_path: PathBuf, valid_paths: &[&str], valid_extensions: &[&str], no_transcode: &[&str], new_launcher: bool) -> Self {
@@ -116,6 +252,54 @@ impl ConfigOptions {
         ConfigOptions::new(game_name,mod_path,data_path,&valid_paths,&valid_extensions,&no_transcode,new_launcher)
     }
 
+    pub fn with_unmergeable_paths(mut self, unmergeable_paths: &[PathBuf]) -> Self {
+        self.unmergeable_paths = unmergeable_paths.to_vec();
+        self
+    }
+
+    pub fn with_override_dir(mut self, override_dir: Option<PathBuf>) -> Self {
+        self.override_dir = override_dir;
+        self
+    }
+
+    pub fn with_encodings(mut self, encodings: BTreeMap<PathBuf,TextEncoding>) -> Self {
+        self.encodings = encodings;
+        self
+    }
+
+    pub fn with_line_ending(mut self, line_ending: Option<LineEnding>) -> Self {
+        self.line_ending = line_ending;
+        self
+    }
+
+    pub fn with_diff_options(mut self, diff_options: DiffOptions, path_diff_options: BTreeMap<PathBuf,DiffOptions>) -> Self {
+        self.diff_options = diff_options;
+        self.path_diff_options = path_diff_options;
+        self
+    }
+
+    /// Settings of the automatic merge for a file, from the most specific folder that has any
+    pub fn diff_options_for(&self, path: &Path) -> DiffOptions {
+        self.path_diff_options.iter()
+            .filter(|(folder,_)| path.starts_with(folder))
+            .max_by_key(|(folder,_)| folder.components().count())
+            .map(|(_,options)| *options)
+            .unwrap_or(self.diff_options)
+    }
+
+    /// Encoding configured for the folder holding a file, if any
+    pub fn encoding_for(&self, path: &Path) -> Option<TextEncoding> {
+        self.encodings.iter()
+            .filter(|(folder,_)| path.starts_with(folder))
+            .max_by_key(|(folder,_)| folder.components().count())
+            .map(|(_,encoding)| *encoding)
+    }
+
+    /// The game's own mod directory, where the launcher looks for descriptors and mod content
+    pub fn game_mod_dir(&self) -> PathBuf {
+        self.mod_path.join("mod")
+    }
+
     pub fn update_paths(self,new_mod_path: PathBuf, new_data_path: PathBuf) -> Self {
         let mut new_options = self;
         new_options.mod_path = new_mod_path;
@@ -181,15 +365,27 @@ fn parse_configs(config_path: &Path) -> Result<Vec<ConfigOptions>,std::io::Error
     Err(std::io::Error::new(std::io::ErrorKind::Other, "This is a config parsing error that should never appear."))
 }
 
-pub fn fetch_user_configs(defaults: bool) -> Result<Vec<ConfigOptions>, Box<dyn std::error::Error>> {
+/// Location of the user's configuration file, merger.toml in the platform's config directory
+pub fn user_config_path() -> PathBuf {
     let user_path = ProjectDirs::from("com", "Parker Okonek", "Paradox Merger").expect("Something went wrong in reading the user dirs.");
-    let _e = fs::create_dir_all(user_path.config_dir())?;
-    let config_path = user_path.config_dir().join("merger.toml");
+    user_path.config_dir().join("merger.toml")
+}
+
+/// Location of a file stored alongside the user's configuration file
+pub fn user_config_sibling(file_name: &str) -> PathBuf {
+    user_config_path().with_file_name(file_name)
+}
+
+pub fn fetch_user_configs(defaults: bool) -> Result<Vec<ConfigOptions>, Box<dyn std::error::Error>> {
+    let config_path = user_config_path();
+    if let Some(config_dir) = config_path.parent() {
+        fs::create_dir_all(config_dir)?;
+    }
 
     let config_file = File::open(&config_path);
     if let Err(e) = config_file {
         if defaults {
-            println!("Generating new default configs");
+            eprintln!("Generating new default configs");
             let configs = generate_default_configs();
             let _ok = store_user_configs(&configs)?;
             Ok(configs)
@@ -205,9 +401,10 @@ pub fn fetch_user_configs(defaults: bool) -> Result<Vec<ConfigOptions>, Box<dyn
 }
 
 pub fn store_user_configs(options: &[ConfigOptions]) -> Result<(),Box< dyn std::error::Error>> {
-    let user_path = ProjectDirs::from("com", "Parker Okonek", "Paradox Merger").expect("Something went wrong in reading the user dirs.");
-    let _e = fs::create_dir_all(user_path.config_dir())?;
-    let config_path = user_path.config_dir().join("merger.toml");
+    let config_path = user_config_path();
+    if let Some(config_dir) = config_path.parent() {
+        fs::create_dir_all(config_dir)?;
+    }
 
     let mut config_file = File::create(&config_path)?;
     let mut config_contents = String::new();
@@ -224,7 +421,8 @@ pub fn store_user_configs(options: &[ConfigOptions]) -> Result<(),Box< dyn std::
     Ok(())
 }
 
-fn generate_default_configs() -> Vec<ConfigOptions> {
+/// Detects every supported game in the Steam library folders and builds a configuration for each
+pub fn generate_default_configs() -> Vec<ConfigOptions> {
     let mut game_paths = HashMap::new();
     let steamapps_dirs = get_all_steam_library_folders();
     for game in supported_games() {
@@ -332,7 +530,7 @@ fn get_all_steam_library_folders() -> Vec<PathBuf> {
     let extra_folders = files::fgrep(&steamapps_dir.join("libraryfolders.vdf"), &RE_VDF_PATH, true);
 
     for extra_folder in extra_folders.iter().map(|s| re::trim_quotes(&s)) {
-        println!("{}",extra_folder);
+        eprintln!("{}",extra_folder);
         library_folders.push(PathBuf::from(extra_folder).join("steamapps/common"));
     }
     
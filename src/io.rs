@@ -1,4 +1,5 @@
 pub mod files;
 pub mod zips;
 pub mod re;
-mod encodings;
\ No newline at end of file
+pub mod staging;
+pub mod encodings;
\ No newline at end of file
@@ -1,43 +1,201 @@
 use encoding::{Encoding, DecoderTrap, EncoderTrap};
 use encoding::all::WINDOWS_1252;
+use serde::{Deserialize,Serialize};
 
-fn decode_latin1(input: &[u8]) -> Option<String> {
-    match WINDOWS_1252.decode(input,DecoderTrap::Strict) {
-        Ok(s) => Some(s),
-        _ => None,
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// Character encoding of a game or mod text file
+#[derive(Serialize,Deserialize,Clone,Copy,Debug,Default,PartialEq,Eq,Hash)]
+pub enum TextEncoding {
+    #[default]
+    #[serde(rename = "utf-8")]
+    Utf8,
+    /// UTF-8 starting with a byte order mark, as the games expect for localisation files
+    #[serde(rename = "utf-8-bom")]
+    Utf8Bom,
+    /// The single byte encoding older games use for most script files
+    #[serde(rename = "windows-1252")]
+    Windows1252,
+}
+
+impl std::fmt::Display for TextEncoding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TextEncoding::Utf8 => write!(f, "UTF-8"),
+            TextEncoding::Utf8Bom => write!(f, "UTF-8 with a byte order mark"),
+            TextEncoding::Windows1252 => write!(f, "Windows-1252"),
+        }
+    }
+}
+
+/// Line terminator of a text file
+#[derive(Serialize,Deserialize,Clone,Copy,Debug,Default,PartialEq,Eq,Hash)]
+pub enum LineEnding {
+    #[serde(rename = "lf")]
+    Lf,
+    /// Windows line endings, which the games themselves ship with
+    #[default]
+    #[serde(rename = "crlf")]
+    CrLf,
+}
+
+/// Text read from a file, along with the encoding it was read in
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub struct DecodedText {
+    /// The text, without any byte order mark
+    pub text: String,
+    pub encoding: TextEncoding,
+    /// Line ending used by most lines of the file as it was read, None if it has a single line
+    pub line_ending: Option<LineEnding>,
+}
+
+/// A character that cannot be written in the encoding a file has to be saved in
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub struct UnencodableCharacter {
+    pub character: char,
+    /// Line of the character, counting from one
+    pub line: usize,
+    /// Column of the character in characters, counting from one
+    pub column: usize,
+    pub encoding: TextEncoding,
+}
+
+impl std::fmt::Display for UnencodableCharacter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "'{}' (U+{:04X}) on line {}, column {} cannot be written as {}", self.character, self.character as u32, self.line, self.column, self.encoding)
+    }
+}
+
+impl std::error::Error for UnencodableCharacter {}
+
+/// Works out the encoding of a file's contents and decodes it
+/// A UTF-8 byte order mark is recorded and removed, other text that is not valid UTF-8 is read as Windows-1252
+/// The five bytes Windows-1252 leaves undefined, 0x81, 0x8D, 0x8F, 0x90 and 0x9D, are read as the C1 control characters of the same value and written back unchanged, so such files still merge
+/// # Arguments
+///
+/// * `input` - raw contents of the file
+///
+/// * `allow_single_byte` - if no, only UTF-8 is accepted
+pub fn decode_text(input: Vec<u8>, allow_single_byte: bool) -> Option<DecodedText> {
+    if input.starts_with(UTF8_BOM) {
+        let text = String::from_utf8(input[UTF8_BOM.len()..].to_vec()).ok()?;
+        return Some(DecodedText {line_ending: detect_line_ending(&text), text, encoding: TextEncoding::Utf8Bom});
+    }
+    let (text,encoding) = match String::from_utf8(input) {
+        Ok(text) => (text,TextEncoding::Utf8),
+        Err(e) if allow_single_byte => (WINDOWS_1252.decode(e.as_bytes(), DecoderTrap::Strict).ok()?,TextEncoding::Windows1252),
+        Err(_) => return None,
+    };
+    Some(DecodedText {line_ending: detect_line_ending(&text), text, encoding})
+}
+
+/// Encodes text for writing, failing on the first character the encoding cannot hold rather than dropping it
+pub fn encode_text(text: &str, encoding: TextEncoding) -> Result<Vec<u8>,UnencodableCharacter> {
+    match encoding {
+        TextEncoding::Utf8 => Ok(text.as_bytes().to_vec()),
+        TextEncoding::Utf8Bom => {
+            let mut bytes = UTF8_BOM.to_vec();
+            bytes.extend_from_slice(text.as_bytes());
+            Ok(bytes)
+        },
+        TextEncoding::Windows1252 => WINDOWS_1252.encode(text, EncoderTrap::Strict).map_err(|_| {
+            first_unencodable(text, encoding).unwrap_or(UnencodableCharacter {character: char::REPLACEMENT_CHARACTER, line: 1, column: 1, encoding})
+        }),
     }
 }
-pub fn encode_latin1(input: String) -> Option<Vec<u8>> {
-    match WINDOWS_1252.encode(&input, EncoderTrap::Strict) {
-        Ok(s) => Some(s),
-        Err(_e) => None,
+
+// Finds where encoding failed, one character at a time, so the error can point at it
+fn first_unencodable(text: &str, encoding: TextEncoding) -> Option<UnencodableCharacter> {
+    for (line_index,line) in text.split('\n').enumerate() {
+        for (column_index,character) in line.chars().enumerate() {
+            let mut buffer = [0; 4];
+            if WINDOWS_1252.encode(character.encode_utf8(&mut buffer), EncoderTrap::Strict).is_err() {
+                return Some(UnencodableCharacter {character, line: line_index + 1, column: column_index + 1, encoding});
+            }
+        }
+    }
+    None
+}
+
+/// The line ending most lines of a text end in, None if no line does
+/// Files mixing both are taken to use Windows line endings if at least half their lines do
+pub fn detect_line_ending(text: &str) -> Option<LineEnding> {
+    let lines = text.matches('\n').count();
+    let windows = text.matches("\r\n").count();
+    match lines {
+        0 => None,
+        _ if windows * 2 >= lines => Some(LineEnding::CrLf),
+        _ => Some(LineEnding::Lf),
     }
 }
 
-fn read_utf8_or_latin1(input: Vec<u8>) -> Option<String> {
-    match String::from_utf8(input.clone()) {
-        Err(_e) => decode_latin1(&input),
-        Ok(s) => Some(s),
+/// Ends every line of a text in the same way
+pub fn convert_line_endings(data: &str, line_ending: LineEnding) -> String {
+    let unix = data.replace("\r\n", "\n");
+    match line_ending {
+        LineEnding::Lf => unix,
+        LineEnding::CrLf => unix.replace('\n', "\r\n"),
     }
 }
 
 pub fn normalize_line_endings(data: String) -> String {
-    data.replace("\r\n", "\n").replace("\n", "\r\n")
+    convert_line_endings(&data, LineEnding::CrLf)
 }
 
 pub fn read_bytes_to_string(input: Vec<u8>, decode: bool, normalize: bool) -> Option<String> {
-    let output: String = if decode {
-        read_utf8_or_latin1(input)?
-    } else {
-        match String::from_utf8(input) {
-            Ok(s) => s,
-            Err(_) => return None,
-        }
-    };
+    Some(read_bytes_to_text(input, decode, normalize)?.text)
+}
+
+/// Like `read_bytes_to_string`, but keeps the encoding the text was found in
+pub fn read_bytes_to_text(input: Vec<u8>, decode: bool, normalize: bool) -> Option<DecodedText> {
+    let mut output = decode_text(input, decode)?;
 
     if normalize {
-        Some(normalize_line_endings(output))
-    } else {
-        Some(output)
+        output.text = normalize_line_endings(output.text);
+    }
+    Some(output)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_detect_and_preserve_encodings() {
+        let latin = decode_text(b"caf\xE9".to_vec(), true).unwrap();
+        assert_eq!((latin.text.as_str(),latin.encoding), ("café",TextEncoding::Windows1252));
+        assert_eq!(decode_text(b"caf\xE9".to_vec(), false), None);
+
+        let bom = decode_text("\u{FEFF}l_english:".as_bytes().to_vec(), true).unwrap();
+        assert_eq!((bom.text.as_str(),bom.encoding), ("l_english:",TextEncoding::Utf8Bom));
+        assert_eq!(encode_text(&bom.text, bom.encoding).unwrap(), "\u{FEFF}l_english:".as_bytes());
+        assert_eq!(encode_text("café", TextEncoding::Windows1252).unwrap(), b"caf\xE9");
+
+        let lost = encode_text("a = b\nname = \"Łódź\"", TextEncoding::Windows1252).unwrap_err();
+        assert_eq!((lost.character,lost.line,lost.column), ('Ł',2,9));
+    }
+
+    #[test]
+    fn test_undefined_windows_1252_bytes_round_trip() {
+        let bytes = b"name = \"caf\xE9 \x81\x8D\x8F\x90\x9D\"\r\n".to_vec();
+        let read = decode_text(bytes.clone(), true).unwrap();
+        assert_eq!((read.text.as_str(),read.encoding), ("name = \"café \u{81}\u{8D}\u{8F}\u{90}\u{9D}\"\r\n",TextEncoding::Windows1252));
+        assert_eq!(encode_text(&read.text, TextEncoding::Windows1252).unwrap(), bytes);
+
+        let lost = encode_text("\u{81}\u{80}", TextEncoding::Windows1252).unwrap_err();
+        assert_eq!((lost.character,lost.column), ('\u{80}',2));
+    }
+
+    #[test]
+    fn test_detect_and_convert_line_endings() {
+        assert_eq!(detect_line_ending("a\nb\n"), Some(LineEnding::Lf));
+        assert_eq!(detect_line_ending("a\r\nb\n"), Some(LineEnding::CrLf));
+        assert_eq!(detect_line_ending("a\r\nb\nc\n"), Some(LineEnding::Lf));
+        assert_eq!(detect_line_ending("a"), None);
+
+        let read = read_bytes_to_text(b"a\nb\n".to_vec(), true, true).unwrap();
+        assert_eq!((read.text.as_str(),read.line_ending), ("a\r\nb\r\n",Some(LineEnding::Lf)));
+        assert_eq!(convert_line_endings(&read.text, LineEnding::Lf), "a\nb\n");
+        assert_eq!(convert_line_endings("a\nb\r\n", LineEnding::CrLf), "a\r\nb\r\n");
     }
 }
@@ -1,31 +1,24 @@
-use crate::io::encodings;
+use crate::io::encodings::{self,DecodedText,TextEncoding};
 use crate::io::re;
 
 use std::path::{PathBuf,Path};
 use std::fs::{self,File};
-use std::io::{prelude::*};
-use std::collections::HashMap;
 use regex::Regex;
 
 pub fn fetch_file_in_path(file_path: &Path, decode: bool, normalize: bool) -> Option<String> {
-    let file = File::open(file_path);
-    if let Ok(file_open) = file {
-        let mut contents = Vec::new();
-        for byte in file_open.bytes() {
-            if let Ok(c) = byte {
-                contents.push(c);
-            } else if let Err(e) = byte {
-                eprintln!("{}",e);
-                return None;
-            }
-        }
-        encodings::read_bytes_to_string(contents,decode,normalize)
-    } else if let Err(e) = file {
-        eprintln!("{}",e);
-        None
-    } else {
-        None
-    }
+    let contents = fetch_bytes_in_path(file_path).ok()?;
+    encodings::read_bytes_to_string(contents,decode,normalize)
+}
+
+/// Reads a text file along with the encoding it was found in
+pub fn fetch_text_in_path(file_path: &Path, decode: bool, normalize: bool) -> Option<DecodedText> {
+    let contents = fetch_bytes_in_path(file_path).ok()?;
+    encodings::read_bytes_to_text(contents,decode,normalize)
+}
+
+/// Reads a file as it is, leaving it to the caller to report why it could not be read
+pub fn fetch_bytes_in_path(file_path: &Path) -> Result<Vec<u8>,std::io::Error> {
+    fs::read(file_path)
 }
 
 pub fn find_even_with_case(path: &Path) -> Option<PathBuf> {
@@ -131,44 +124,8 @@ pub fn list_files_in_dir(dir: &Path, extensions: &[&Path], relative: bool) -> Ve
     results
 }
 
-pub fn relative_folder_path(mod_folder: &Path, path: &Path) -> Result<PathBuf,std::io::Error> {
-    let current_dir = std::env::current_dir()?;
-    let mod_folder = Path::new(&mod_folder);
-    let full_path: PathBuf = [&current_dir,mod_folder,path].iter().collect();
-    Ok(full_path)
-}
-
-#[allow(dead_code)]
-pub fn fetch_file_in_relative_path(path_base: &Path, rel_path: &Path) -> Option<String> {
-    let full_path: PathBuf = path_base.join(rel_path);
-    fetch_file_in_path(&full_path,false,false)
-}
-
-pub fn fetch_all_files_in_path(path: &Path) -> HashMap<String,Vec<u8>>{
-    let mut results = HashMap::new();
-    let folder_path = match find_even_with_case(&path) {
-        Some(p) => p,
-        None => return results,
-    };
-    let all_files_rel = walk_in_dir(&folder_path, Some(&folder_path));
-    let all_files_abs = walk_in_dir(&folder_path, None);
-
-    for (rel_path,file_path) in all_files_rel.iter().zip(all_files_abs) {
-        let file = File::open(file_path);
-        let real_path: String = match rel_path.to_str() {
-            Some(s) => s.to_owned(),
-            None => {eprintln!("Could not read file path for {}",rel_path.display()); continue},
-        };
-        if let Ok(mut file_open) = file {
-            let mut contents = Vec::new();
-            match file_open.read_to_end(&mut contents) {
-                Ok(_) => {results.insert(real_path, contents);},
-                Err(e) => eprintln!("{}",e),
-            };
-        }
-    }
-
-    results
+pub fn relative_folder_path(output_root: &Path, mod_folder: &Path, path: &Path) -> PathBuf {
+    [output_root,mod_folder,path].iter().collect()
 }
 
 pub fn write_file_with_content(file_path: &Path, file_content: &[u8]) -> Result<(),std::io::Error> {
@@ -183,30 +140,31 @@ pub fn write_file_with_content(file_path: &Path, file_content: &[u8]) -> Result<
 }
 
 pub fn write_file_with_string<P: AsRef<Path>>(file_path: P, file_content: String, encode: bool) -> Result<(),std::io::Error> {
-    let content = if encode {
-        encodings::encode_latin1(file_content)
-    } else {
-        Some(file_content.as_bytes().to_vec())
-    };
-    match content {
-        Some(bytes) => write_file_with_content(file_path.as_ref(), &bytes),
-        None => Err(std::io::Error::from_raw_os_error(126)),
-    }
+    let encoding = if encode { TextEncoding::Windows1252 } else { TextEncoding::Utf8 };
+    write_file_with_encoding(file_path, &file_content, encoding)
 }
 
-pub fn copy_directory_tree<P: AsRef<Path>>(source_dir: P, result_dir: P, overwrite: bool, _ignore_direct: bool) -> Result<(),std::io::Error> {
-    //let from_files_abs = walk_in_dir(source_dir, None);
-    let from_files_rel = walk_in_dir(source_dir.as_ref(), Some(source_dir.as_ref()));
-
-    for file in from_files_rel {
-        let from_abs_path = source_dir.as_ref().join(&file);
-        let to_abs_path = result_dir.as_ref().join(file);
-
-        if !overwrite || !to_abs_path.exists() {
-            fs::copy(from_abs_path,to_abs_path)?;
-        }
+/// Writes text in the given encoding, creating any missing parent directories
+/// Text the encoding cannot hold is an `InvalidData` error naming the file and the first such character, and nothing is written
+pub fn write_file_with_encoding<P: AsRef<Path>>(file_path: P, file_content: &str, encoding: TextEncoding) -> Result<(),std::io::Error> {
+    match encodings::encode_text(file_content, encoding) {
+        Ok(bytes) => write_file_with_content(file_path.as_ref(), &bytes),
+        Err(e) => Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("{}: {}",file_path.as_ref().display(),e))),
     }
+}
 
+/// Copies a single file, creating any missing parent directories and overwriting an existing file
+/// # Arguments
+/// 
+/// * `source` - file to copy
+/// 
+/// * `destination` - path of the new file
+/// 
+pub fn copy_file<P: AsRef<Path>>(source: P, destination: P) -> Result<(),std::io::Error> {
+    if let Some(parent) = destination.as_ref().parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::copy(source,destination)?;
     Ok(())
 }
 
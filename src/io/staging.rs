@@ -0,0 +1,148 @@
+use std::fs;
+use std::path::Path;
+
+/// Moves the patch entries of a staging directory into the output directory, keeping any previous patch as a backup
+/// Every entry is moved with a rename, so the staging directory must be on the same file system as the output directory
+/// If anything fails part way through, the previous patch is moved back into place
+/// A backup left by an earlier run is dropped even when there is no previous patch, so restoring never skips back past the patch just replaced
+/// # Arguments
+///
+/// * `staging_dir` - directory the new patch was generated in
+///
+/// * `output_root` - directory the patch is being installed into
+///
+/// * `patch_entries` - names of every file and folder that belongs to a patch
+///
+/// * `backup_dir` - directory the previous patch is moved into
+///
+pub fn swap_into_place(staging_dir: &Path, output_root: &Path, patch_entries: &[String], backup_dir: &Path) -> Result<(),std::io::Error> {
+    let has_previous = patch_entries.iter().any(|entry| exists(&output_root.join(entry)));
+    if has_previous {
+        if exists(backup_dir) {
+            fs::remove_dir_all(backup_dir)?;
+        }
+        fs::create_dir_all(backup_dir)?;
+        move_entries(output_root, backup_dir, patch_entries)?;
+    }
+
+    if let Err(e) = move_entries(staging_dir, output_root, patch_entries) {
+        // Put the new entries back and the old ones where they were
+        let _ = move_entries(output_root, staging_dir, patch_entries);
+        if has_previous {
+            let _ = move_entries(backup_dir, output_root, patch_entries);
+        }
+        return Err(e);
+    }
+
+    if !has_previous && exists(backup_dir) {
+        fs::remove_dir_all(backup_dir)?;
+    }
+    fs::remove_dir_all(staging_dir)
+}
+
+/// Replaces the current patch with the one kept in the backup directory
+/// Returns false if there is no backup to restore
+/// # Arguments
+///
+/// * `output_root` - directory the patch is installed in
+///
+/// * `patch_entries` - names of every file and folder that belongs to a patch
+///
+/// * `backup_dir` - directory the previous patch was moved into
+///
+pub fn restore_backup(output_root: &Path, patch_entries: &[String], backup_dir: &Path) -> Result<bool,std::io::Error> {
+    if !exists(backup_dir) {
+        return Ok(false);
+    }
+
+    for entry in patch_entries {
+        remove_entry(&output_root.join(entry))?;
+    }
+    move_entries(backup_dir, output_root, patch_entries)?;
+    fs::remove_dir_all(backup_dir)?;
+    Ok(true)
+}
+
+/// Removes a staging directory left behind by a failed run
+pub fn discard(staging_dir: &Path) -> Result<(),std::io::Error> {
+    remove_entry(staging_dir)
+}
+
+fn move_entries(from_dir: &Path, to_dir: &Path, patch_entries: &[String]) -> Result<(),std::io::Error> {
+    for entry in patch_entries {
+        let from = from_dir.join(entry);
+        if exists(&from) {
+            fs::rename(from, to_dir.join(entry))?;
+        }
+    }
+    Ok(())
+}
+
+fn remove_entry(path: &Path) -> Result<(),std::io::Error> {
+    if path.is_dir() {
+        fs::remove_dir_all(path)
+    } else if exists(path) {
+        fs::remove_file(path)
+    } else {
+        Ok(())
+    }
+}
+
+fn exists(path: &Path) -> bool {
+    fs::symlink_metadata(path).is_ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    #[test]
+    fn test_swap_and_restore() {
+        let dir = TempDir::new("staging");
+        let root = dir.path();
+        let staging_dir = root.join(".patch.staging");
+        let backup_dir = root.join(".patch.backup");
+        let entries = vec!["patch.mod".to_owned(), "patch".to_owned()];
+        fs::create_dir_all(root.join("patch")).unwrap();
+        fs::write(root.join("patch.mod"), "old").unwrap();
+        fs::create_dir_all(staging_dir.join("patch")).unwrap();
+        fs::write(staging_dir.join("patch.mod"), "new").unwrap();
+
+        swap_into_place(&staging_dir, root, &entries, &backup_dir).unwrap();
+        assert_eq!(fs::read_to_string(root.join("patch.mod")).unwrap(), "new");
+        assert_eq!(fs::read_to_string(backup_dir.join("patch.mod")).unwrap(), "old");
+        assert!(!staging_dir.exists());
+
+        assert!(restore_backup(root, &entries, &backup_dir).unwrap());
+        assert_eq!(fs::read_to_string(root.join("patch.mod")).unwrap(), "old");
+        assert!(!restore_backup(root, &entries, &backup_dir).unwrap());
+    }
+
+    #[test]
+    fn test_swap_without_previous_patch_drops_old_backup() {
+        let dir = TempDir::new("stale-backup");
+        let root = dir.path();
+        let staging_dir = root.join(".patch.staging");
+        let backup_dir = root.join(".patch.backup");
+        let entries = vec!["patch.mod".to_owned()];
+        let stage = |contents: &str| {
+            fs::create_dir_all(&staging_dir).unwrap();
+            fs::write(staging_dir.join("patch.mod"), contents).unwrap();
+        };
+        fs::write(root.join("patch.mod"), "first").unwrap();
+
+        stage("second");
+        swap_into_place(&staging_dir, root, &entries, &backup_dir).unwrap();
+        assert_eq!(fs::read_to_string(backup_dir.join("patch.mod")).unwrap(), "first");
+
+        // The installed patch was removed by hand, so the next one has nothing to replace
+        fs::remove_file(root.join("patch.mod")).unwrap();
+        stage("third");
+        swap_into_place(&staging_dir, root, &entries, &backup_dir).unwrap();
+        assert!(!backup_dir.exists());
+
+        assert!(!restore_backup(root, &entries, &backup_dir).unwrap());
+        assert_eq!(fs::read_to_string(root.join("patch.mod")).unwrap(), "third");
+    }
+}
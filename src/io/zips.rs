@@ -1,88 +1,101 @@
-use crate::io::encodings;
 use crate::io::files::find_even_with_case;
 
 use std::path::{Path};
-use std::fs::{File};
-use std::io::{prelude::*,BufReader};
-use std::collections::HashMap;
+use std::fs::{self,File};
+use std::io::{prelude::*,BufReader,BufWriter};
+use std::collections::HashSet;
+use zip::CompressionMethod;
 use zip::read::ZipArchive;
-use zip::write::ZipWriter;
+use zip::write::{FileOptions,ZipWriter};
 
-pub fn zip_fetch_file_relative(file_path: &Path, zip_archive: &Path, decode: bool, normalize: bool) -> Option<String> {
-    let zip_path = find_even_with_case(&zip_archive)?;
-        let file = match File::open(&zip_path) {
-            Ok(f) => f,
-            Err(e) => {eprintln!("{}",e); return None},
-        };
-        let reader = BufReader::new(file);
-        let mut zip_file = match ZipArchive::new(reader) {
-            Ok(r) => r,
-            Err(e) => {eprintln!("{}",e); return None},
-        };
-        let zip_content = zip_file.by_name(&file_path.to_str()?);
-        
-        if let Ok(content) = zip_content {
-            if content.is_dir() {
-                return None;
-            }
-            let mut output = Vec::new();
-            for byte in content.bytes() {
-                if let Ok(c) = byte {
-                    output.push(c);
-                } else if let Err(e) = byte {
-                    eprintln!("{}",e);
-                    return None;
-                }
-            }
-            encodings::read_bytes_to_string(output,decode,normalize)
-        } else {
-            None
-        }
+/// Opens a zip archive for reading, matching the archive name without regard to case
+/// # Arguments
+/// 
+/// * `zip_archive` - path of the zip file to open
+/// 
+pub fn zip_open(zip_archive: &Path) -> Result<ZipArchive<BufReader<File>>,std::io::Error> {
+    let zip_path = match find_even_with_case(zip_archive) {
+        Some(p) => p,
+        None => return Err(std::io::Error::new(std::io::ErrorKind::NotFound, format!("Could not find archive {}",zip_archive.display()))),
+    };
+    let file = File::open(&zip_path)?;
+    let reader = BufReader::new(file);
+    ZipArchive::new(reader).map_err(std::io::Error::from)
 }
 
-pub fn zip_fetch_all_files(zip_archive: &Path) -> HashMap<String,Vec<u8>> {
-    let mut results = HashMap::new();
-    let zip_path = find_even_with_case(zip_archive);
-    if let Some(good_path) = zip_path {
-        let file = match File::open(good_path) {
-            Ok(p) => p,
-            Err(e) => {eprintln!("{}",e); return results},
-        };
-        let reader = BufReader::new(file);
-        let mut zip_file = match ZipArchive::new(reader) {
-            Ok(z) => z,
-            Err(e) => {eprintln!("{}",e); return results},
-        };
-        let names: Vec<String> = zip_file.file_names().map(|x| x.to_owned()).collect();
-        for full_path in names {
-            let zip_result = zip_file.by_name(&full_path);
-            if let Ok(mut zip_file_content) = zip_result {
-                if zip_file_content.is_file() {
-                    let mut buf = Vec::new();
-                    let out = zip_file_content.read_to_end(&mut buf);
-                    match out {
-                        Ok(_) => {results.insert(full_path, buf);},
-                        Err(e) => {eprintln!("{}",e);},
-                    }
-                }
-            } else {
-                eprintln!("File could not be extracted! {}",full_path);
-            }
-        }
+/// Reads the full contents of a single file in an already opened zip archive
+/// Fails if the entry does not exist, is a directory or can't be decompressed
+/// # Arguments
+/// 
+/// * `zip_file` - opened zip archive
+/// 
+/// * `entry_name` - exact name of the entry inside the archive
+/// 
+pub fn zip_read_entry<R: Read + Seek>(zip_file: &mut ZipArchive<R>, entry_name: &str) -> Result<Vec<u8>,std::io::Error> {
+    let mut content = zip_file.by_name(entry_name)?;
+    if content.is_dir() {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("{} is a directory",entry_name)));
     }
-    results
+    let mut output = Vec::new();
+    content.read_to_end(&mut output)?;
+    Ok(output)
 }
 
-pub fn zip_write_files(zip_path: &Path, staged_data: HashMap<String,Vec<u8>>) -> Result<(),std::io::Error> {
-    let file = File::open(&zip_path)?;
-    let mut writer = ZipWriter::new(file);
-    let options = zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Stored);
+/// Writes a new zip archive one entry at a time, compressing every entry with Deflate
+/// Entries are streamed straight into the archive, so only the names written so far are kept in memory
+pub struct ZipStreamWriter {
+    writer: ZipWriter<BufWriter<File>>,
+    written: HashSet<String>,
+}
+
+impl ZipStreamWriter {
+    /// Creates the archive and any missing parent directories, replacing an existing file
+    /// # Arguments
+    /// 
+    /// * `zip_path` - path of the zip file to create
+    /// 
+    pub fn create(zip_path: &Path) -> Result<Self,std::io::Error> {
+        if let Some(parent) = zip_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let file = File::create(zip_path)?;
+        Ok(ZipStreamWriter {writer: ZipWriter::new(BufWriter::new(file)), written: HashSet::new()})
+    }
+
+    /// Begins a new entry that following writes will fill
+    /// Returns false without starting anything if an entry with the same name, ignoring case, was already written
+    /// # Arguments
+    /// 
+    /// * `entry_name` - relative path of the entry, separators are converted to forward slashes
+    /// 
+    pub fn start_entry(&mut self, entry_name: &str) -> Result<bool,std::io::Error> {
+        let entry_name = entry_name.replace('\\', "/");
+        if !self.written.insert(entry_name.to_ascii_lowercase()) {
+            return Ok(false);
+        }
+        let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+        self.writer.start_file(entry_name, options)?;
+        Ok(true)
+    }
 
-    for (file_path,file_data) in staged_data {
-        writer.start_file_from_path(Path::new(&file_path), options)?;
-        writer.write_all(&file_data)?;
+    /// Checks whether an entry with the same name, ignoring case, was already written
+    pub fn contains(&self, entry_name: &str) -> bool {
+        self.written.contains(&entry_name.replace('\\', "/").to_ascii_lowercase())
     }
 
-    writer.finish()?;
-    Ok(())
-}
\ No newline at end of file
+    /// Writes the central directory and closes the archive
+    pub fn finish(mut self) -> Result<(),std::io::Error> {
+        let mut file = self.writer.finish()?;
+        file.flush()
+    }
+}
+
+impl Write for ZipStreamWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.writer.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+}
@@ -1,13 +1,34 @@
 mod moddata;
 mod merge_diff;
 mod io;
+mod pool;
+mod progress;
+mod manifest;
+mod report;
+mod playsets;
+mod line_diff;
+mod unified_diff;
+mod resolutions;
+mod overrides;
+mod benchmark;
+#[cfg(test)]
+mod test_support;
 pub mod configs;
 
-pub use moddata::{mod_info::ModInfo,mod_pack::ModPack,mod_pack::ModStatus,mod_pack::ModToken};
+pub use moddata::{mod_info::ModInfo,mod_pack::ModPack,mod_pack::ModStatus,mod_pack::ModToken,mod_conflict::ModConflict,mod_reader::ModReader,conflict_matrix::ConflictMatrix,conflict_matrix::ModPairConflicts};
+pub use progress::{ProgressEvent,ProgressReporter,ProgressStage,SilentProgress};
+pub use manifest::{ManifestEntry,ManifestInput,MergeStrategy,PatchManifest,PreviousPatch,MANIFEST_NAME};
+pub use report::{ConflictReport,HunkReport,MergeOutcome,MergeReport};
+pub use playsets::{PlaysetStore,PLAYSETS_NAME};
+pub use resolutions::{Resolution,ResolutionChoice,ResolutionPolicy,ResolutionStore,UnresolvedConflict,RESOLUTIONS_NAME};
+pub use merge_diff::{ConflictHunk,DiffOptions,MergeChunk};
+pub use benchmark::{score_fixtures,BenchmarkScore,MergeFixture};
+pub use overrides::{OverrideDir,OverrideRecord,OverrideReport,OVERRIDE_RECORD_NAME};
+pub use io::encodings::{LineEnding,TextEncoding};
 
 use std::path::{PathBuf,Path};
 use std::fs::{self,File};
-use std::io::{BufReader};
+use std::io::{BufReader,Write};
 use std::collections::HashMap;
 
 use lazy_static::lazy_static;
@@ -15,12 +36,18 @@ use regex::Regex;
 
 use zip::read::ZipArchive;
 
-use merge_diff::diff_single_conflict;
+use merge_diff::{diff_single_conflict_with,merge_lines,DiffOutcome};
 
-use io::{files,zips,re};
-use configs::{ArgOptions,ConfigOptions};
+use io::{encodings,files,zips,re,staging};
+use io::encodings::DecodedText;
+use configs::{ArgOptions,ConfigOptions,PatchFormat};
 
 
+/// Unchanged lines shown around each change in a unified diff
+const DIFF_CONTEXT: usize = 3;
+/// Folder of the `_bad` folder holding every hunk that merged when others did not
+const PARTIAL_MERGE_FOLDER: &str = "partial_merge";
+
 lazy_static! {
     // Evaluate all of our regular expressions just once for efficiency and things only dying the first time
     static ref RE_DEPS: Regex     = Regex::new(r#"(?m)dependencies[^}]+"#).unwrap();
@@ -31,6 +58,7 @@ lazy_static! {
     static ref RE_REPLACE: Regex  = Regex::new(r#"replace_path\s*=\s*"[^"]*""#).unwrap();
     static ref RE_MOD: Regex      = Regex::new("\"mod/[^\"]*\"").unwrap();
     static ref RE_USER_DIR: Regex = Regex::new(r#"user_dir\s*=\s*"[^"]*""#).unwrap();
+    static ref RE_VERSION: Regex  = Regex::new(r#"(?m)^\s*version\s*=\s*"[^"]*""#).unwrap();
 }
 
 /// Given a path to a Paradox mod description file, generate a list of all its dependencies
@@ -57,7 +85,9 @@ fn collect_dependencies(mod_path: &Path) -> Vec<String> {
 /// * `mod_path` - parent path to both the mod content and mod descriptor file
 /// 
 /// * `mod_file` - mod descriptor file name and extension
-fn generate_single_mod(mod_path: &Path, mod_file: &Path) -> Option<ModInfo> {
+/// 
+/// * `progress` - receives warnings about malformed descriptors
+fn generate_single_mod(mod_path: &Path, mod_file: &Path, progress: &dyn ProgressReporter) -> Option<ModInfo> {
         let modmod_path: PathBuf = mod_path.join(mod_file);
         let dependencies = collect_dependencies(&modmod_path);
 
@@ -68,11 +98,13 @@ fn generate_single_mod(mod_path: &Path, mod_file: &Path) -> Option<ModInfo> {
         let name: Vec<String> = re::grep(&modmod_content, &RE_NAMES, false).iter().map(|x| re::trim_quotes(x)).collect();
         let replace_paths: Vec<PathBuf> = re::grep(&modmod_content, &RE_REPLACE, true).iter().map(|x| PathBuf::from(re::trim_quotes(x))).collect();
         let user_dir: Option<String> = re::grep(&modmod_content, &RE_USER_DIR, false).iter().map(|x| re::trim_quotes(x)).next();
+        let version: Option<String> = re::grep(&modmod_content, &RE_VERSION, false).iter().map(|x| re::trim_quotes(x)).next();
         
         //let path: Vec<String> = path.into_iter().filter(|x| !&replace_paths.contains(&PathBuf::from(&x))).collect();
         
         if archive.is_empty() && path.is_empty() || !archive.is_empty() && !path.is_empty() {
-            eprintln!("{}\n Spaghetti-Os: {} {}", &modmod_path.display(), archive.is_empty(), path.is_empty());
+            let message = format!("{} must list exactly one of archive or path (archive missing: {}, path missing: {})", modmod_path.display(), archive.is_empty(), path.is_empty());
+            progress.warn(ProgressStage::Scanning, &message);
             None
         } else if name.len() == 1 && archive.len() == 1 {
             let zip_path: PathBuf = mod_path.join(&archive[0]);
@@ -80,23 +112,23 @@ fn generate_single_mod(mod_path: &Path, mod_file: &Path) -> Option<ModInfo> {
             let zip_path = files::find_even_with_case(&zip_path)?;
             let file = match File::open(&zip_path) {
                 Ok(f) => f,
-                Err(e) => {eprintln!("{}",e); return None},
+                Err(e) => {progress.warn(ProgressStage::Scanning, &format!("{}: {}",zip_path.display(),e)); return None},
             };
             let reader = BufReader::new(file);
             let zipfile = match ZipArchive::new(reader) {
                 Ok(z) => z,
-                Err(e) => {eprintln!("{}",e); return None},
+                Err(e) => {progress.warn(ProgressStage::Scanning, &format!("{}: {}",zip_path.display(),e)); return None},
             };
             
             let files: Vec<&str> = zipfile.file_names().collect();
             
-            Some(ModInfo::new(mod_file.to_path_buf(),&files,zip_path,name[0].clone(),&dependencies,&replace_paths,user_dir,true))
+            Some(ModInfo::new(mod_file.to_path_buf(),&files,zip_path,name[0].clone(),&dependencies,&replace_paths,user_dir,true).with_version(version))
         } else if name.len() == 1 && path.len() == 1 {
             let dir_path: PathBuf = mod_path.join(&path[0]);
             let dir_path = files::find_even_with_case(&dir_path)?;
             let file_check = files::walk_in_dir(&dir_path,Some(&dir_path));
             let files_ref: Vec<&str> = file_check.iter().map(|x| x.to_str().unwrap_or_default()).collect();
-            Some(ModInfo::new(mod_file.to_path_buf(),&files_ref,dir_path,name[0].clone(),&dependencies,&replace_paths,user_dir,true))
+            Some(ModInfo::new(mod_file.to_path_buf(),&files_ref,dir_path,name[0].clone(),&dependencies,&replace_paths,user_dir,true).with_version(version))
         } else {
             None
         }
@@ -106,58 +138,95 @@ fn generate_single_mod(mod_path: &Path, mod_file: &Path) -> Option<ModInfo> {
 /// #Arguments
 /// 
 /// * `path` - Path of the game's user directory, typically in Documents or ~/.Paradox\ Interactive/
-pub fn generate_enabled_mod_list(path: &Path, new_launcher: bool) -> Vec<ModInfo> {
-    let enabled_mods = list_enabled_mods(path,new_launcher);
+/// 
+/// * `progress` - receives an event for every mod descriptor read
+pub fn generate_enabled_mod_list(path: &Path, new_launcher: bool, progress: &dyn ProgressReporter) -> Vec<ModInfo> {
+    let enabled_mods = list_enabled_mods(path,new_launcher,progress);
     let mut mods = Vec::new();
     
+    progress.report(ProgressEvent::Started { stage: ProgressStage::Scanning, total: enabled_mods.len() });
     for i in enabled_mods {
         let mod_file = PathBuf::from(i);
-        let smod = generate_single_mod(&path,&mod_file);
+        let smod = generate_single_mod(path,&mod_file,progress);
         if let Some(good_mod) = smod {
+            progress.report(ProgressEvent::Processed { stage: ProgressStage::Scanning, path: &mod_file, mod_name: Some(good_mod.get_name()) });
             mods.push(good_mod);
         } else {
-            eprintln!("The following mod failed to load:\t{}",mod_file.display());
+            progress.warn(ProgressStage::Scanning, &format!("The following mod failed to load:\t{}",mod_file.display()));
         }
     }
+    progress.report(ProgressEvent::Finished { stage: ProgressStage::Scanning });
 
     mods
 }
 
 /// Generate a list of all mods, enabled or not
-pub fn generate_entire_mod_list(path: &Path, new_launcher: bool) -> Vec<ModInfo> {
+/// #Arguments
+/// 
+/// * `path` - Path of the game's user directory, typically in Documents or ~/.Paradox\ Interactive/
+/// 
+/// * `progress` - receives an event for every mod descriptor read
+pub fn generate_entire_mod_list(path: &Path, new_launcher: bool, progress: &dyn ProgressReporter) -> Vec<ModInfo> {
     let mut mod_list: Vec<ModInfo> = Vec::new();
     let mod_ext = PathBuf::from("mod");
     let mod_mod_ext = PathBuf::from("mod.mod");
-    let enabled_mods: Vec<String> = list_enabled_mods(path, new_launcher);
+    let enabled_mods: Vec<String> = list_enabled_mods(path, new_launcher, progress);
 
     let mods_path = path.join("mod");
     let s_mod_path = PathBuf::from(path);
 
-    for mod_file in files::list_files_in_dir(&mods_path,&[&mod_ext,&mod_mod_ext],true) {
+    let mod_files = files::list_files_in_dir(&mods_path,&[&mod_ext,&mod_mod_ext],true);
+    progress.report(ProgressEvent::Started { stage: ProgressStage::Scanning, total: mod_files.len() });
+    for mod_file in mod_files {
         let mod_mod = PathBuf::from("mod");
         let mod_file = mod_mod.join(mod_file);
-        let s_mod = generate_single_mod(&s_mod_path, &mod_file);
+        let s_mod = generate_single_mod(&s_mod_path, &mod_file, progress);
         if let Some(good_mod) = s_mod {
+            progress.report(ProgressEvent::Processed { stage: ProgressStage::Scanning, path: &mod_file, mod_name: Some(good_mod.get_name()) });
 
-            let enabled = enabled_mods.iter().any(|file| mod_file == PathBuf::from(file.as_str()));
+            let enabled = enabled_mods.iter().any(|file| mod_file == Path::new(file));
             mod_list.push(good_mod.with_active_state(enabled));
         } else {
-            eprintln!("The following mod failed to load:\t{}",mod_file.display());
+            progress.warn(ProgressStage::Scanning, &format!("The following mod failed to load:\t{}",mod_file.display()));
         }
     }
+    progress.report(ProgressEvent::Finished { stage: ProgressStage::Scanning });
 
     mod_list
 }
 
-pub fn set_entire_mod_list(path: &Path, new_launcher: bool, mod_list: &[ModStatus]) -> Result<(),Box<dyn std::error::Error>> {
+/// Write the enabled mods of a load order back to the game's launcher settings
+/// #Arguments
+/// 
+/// * `path` - Path of the game's user directory, typically in Documents or ~/.Paradox\ Interactive/
+/// 
+/// * `new_launcher` - if yes, the enabled mods are kept in dlc_load.json, otherwise in settings.txt
+/// 
+/// * `mod_list` - every mod in load order, only active mods are written
+/// 
+/// * `progress` - receives a warning if the settings cannot be read or are not laid out as expected, leaving them untouched
+pub fn set_entire_mod_list(path: &Path, new_launcher: bool, mod_list: &[ModStatus], progress: &dyn ProgressReporter) -> Result<(),Box<dyn std::error::Error>> {
+    let stage = ProgressStage::Scanning;
     if new_launcher {
-        println!("New Launcher");
+        let settings = path.join("dlc_load.json");
+        // Keep every other setting, such as disabled DLC, as it was
+        let mut all_settings: serde_json::Map<String,serde_json::Value> = match files::fetch_file_in_path(&settings, false, false) {
+            Some(s) => serde_json::from_str(&s)?,
+            None => serde_json::Map::new(),
+        };
+        let enabled: Vec<serde_json::Value> = mod_list.iter()
+            .filter(|item| item.status())
+            .filter_map(|item| item.mod_file().to_str())
+            .map(|item| serde_json::Value::String(item.replace('\\', "/")))
+            .collect();
+        all_settings.insert("enabled_mods".to_owned(), serde_json::Value::Array(enabled));
+        files::write_file_with_string(&settings, serde_json::to_string(&all_settings)?, false)?;
     } else {
         let settings = path.join("settings.txt");
         let old_settings_str = match files::fetch_file_in_path(&settings, false, true) {
             Some(s) => s,
             None => {
-                eprintln!("Had an issue finding and reading the settings file.");
+                progress.warn(stage, &format!("Could not read the launcher settings in {}",settings.display()));
                 return Ok(());
             },
         };
@@ -166,14 +235,14 @@ pub fn set_entire_mod_list(path: &Path, new_launcher: bool, mod_list: &[ModStatu
 
         let chunks: Vec<_> = old_settings_str.splitn(2,"last_mods=\r\n{").collect();
         if chunks.len() != 2 {
-            eprintln!("Wrong number of chunks");
+            progress.warn(stage, &format!("The launcher settings in {} have no list of mods",settings.display()));
             return Ok(());
         }
 
         let chunks_boogaloo: Vec<_> = chunks[1].splitn(2,"}\r\n").collect();
 
         if chunks_boogaloo.len() != 2 {
-            eprintln!("Wrong number of boogaloo chunks");
+            progress.warn(stage, &format!("The list of mods in the launcher settings in {} is never closed",settings.display()));
             return Ok(());
         }
 
@@ -183,7 +252,7 @@ pub fn set_entire_mod_list(path: &Path, new_launcher: bool, mod_list: &[ModStatu
         for item in mod_list {
             if let Some(s) = item.to_str() {
                 output.push('"');
-                output.push_str(&s);
+                output.push_str(s);
                 output.push_str("\"\r\n");
             }
         }
@@ -191,23 +260,24 @@ pub fn set_entire_mod_list(path: &Path, new_launcher: bool, mod_list: &[ModStatu
         output.push_str("}\r\n");
         output.push_str(chunks_boogaloo[1]);
 
-        let res = files::write_file_with_string(&settings, output, false)?;
-
+        files::write_file_with_string(&settings, output, false)?;
     }
-return Ok(())
+    Ok(())
 }
 
-fn list_enabled_mods(path: &Path, new_launcher: bool) -> Vec<String> {
+// Reads the mods enabled in the game's launcher settings, warning and taking none to be enabled if they cannot be read
+fn list_enabled_mods(path: &Path, new_launcher: bool, progress: &dyn ProgressReporter) -> Vec<String> {
+    let stage = ProgressStage::Scanning;
     if new_launcher {
         let settings = path.join("dlc_load.json");
 
-        let all_mods_str = match files::fetch_file_in_path(&settings, false, false) {
-            Some(s) => s,
-            None => {eprintln!("Had an issue finding and reading the settings file."); return Vec::new()},
+        let all_mods_str = match files::fetch_bytes_in_path(&settings) {
+            Ok(bytes) => String::from_utf8_lossy(&bytes).into_owned(),
+            Err(e) => {progress.warn(stage, &format!("Could not read the launcher settings in {}: {}",settings.display(),e)); return Vec::new()},
         };
         let all_mods: HashMap<String,Vec<String>> = match serde_json::from_str(&all_mods_str) {
             Ok(val) => val,
-            Err(e) => {eprintln!("{:?}",e); return Vec::new()},
+            Err(e) => {progress.warn(stage, &format!("Could not parse the launcher settings in {}: {}",settings.display(),e)); return Vec::new()},
         };
 
         match all_mods.get("enabled_mods") {
@@ -216,13 +286,12 @@ fn list_enabled_mods(path: &Path, new_launcher: bool) -> Vec<String> {
         }
     } else {
         let settings = path.join("settings.txt");
-        let enabled_mods = files::fgrep(&settings,&RE_MOD,true);
-
-        if enabled_mods.is_empty() {
-            eprintln!("Had an issue reading the settings file.");
-            Vec::new()
-        } else {
-            enabled_mods.iter().map(|s| re::trim_quotes(s)).collect()
+        match files::fetch_file_in_path(&settings, true, true) {
+            Some(settings_str) => re::grep(&settings_str,&RE_MOD,true).iter().map(|s| re::trim_quotes(s)).collect(),
+            None => {
+                progress.warn(stage, &format!("Could not read the launcher settings in {}",settings.display()));
+                Vec::new()
+            },
         }
     }
 }
@@ -251,6 +320,9 @@ pub fn files_in_vanilla(config: &ConfigOptions) -> Vec<PathBuf> {
 
 /// Performs an automagical merge of the current list of conflicting mods
 /// This can fail for some files, but those files will be placed in their own directory tree for easy manual merging
+/// Conflicts are merged in parallel across `args.jobs` worker threads, but results are gathered in conflict order
+/// A manifest of every conflict and the hashes of its inputs is written to the root of the patch folder
+/// Returns a report of how every conflict was merged, in conflict order
 /// 
 /// #Arguments
 /// 
@@ -259,112 +331,525 @@ pub fn files_in_vanilla(config: &ConfigOptions) -> Vec<PathBuf> {
 /// * `args` - options left over from arguments, will be removed soon
 /// 
 /// * `mod_pack` - the current mod load order to be merged 
-pub fn auto_merge(config: &ConfigOptions, args: &ArgOptions, mod_pack: &ModPack) -> Result<u32,()> {
-    let mut successful = 0;
+/// 
+/// * `reader` - shared reader for mod contents, so each mod archive is only opened once
+/// 
+/// * `previous` - patch from an earlier run, whose output is reused for conflicts with unchanged inputs
+/// 
+/// * `resolutions` - saved resolutions for hunks the automatic merge cannot settle, if any
+/// 
+/// * `progress` - receives an event for every conflict and every mod file read, as well as any warnings
+pub fn auto_merge(config: &ConfigOptions, args: &ArgOptions, mod_pack: &ModPack, reader: &ModReader, previous: Option<&PreviousPatch>, resolutions: Option<&ResolutionStore>, progress: &dyn ProgressReporter) -> Result<MergeReport,()> {
+    let stage = ProgressStage::Merging;
+    let output_root = match args.output_root() {
+        Ok(root) => root,
+        Err(e) => {
+            progress.warn(stage, &format!("Could not resolve the output directory: {}",e));
+            return Err(());
+        },
+    };
+    let mut overrides = match open_overrides(config) {
+        Ok(overrides) => overrides,
+        Err(e) => {
+            progress.warn(stage, &e.to_string());
+            return Err(());
+        },
+    };
+    warn_ignored_settings(config, progress);
+    let context = MergeContext {config, args, output_root: &output_root, mod_pack, reader, previous, resolutions, overrides: overrides.as_ref(), progress};
 
-        for conf in mod_pack.list_conflicts() {
-            if args.verbose {
-                println!("Attempting to merge: {}",conf.path().display());
-            }
-            let mut file_contents: Vec<String> = Vec::new();
-            let mut file_indices: Vec<usize> = Vec::new();
-            let should_transcode = match conf.path().extension() {
-                Some(ext) => !config.no_transcode.iter().any(|no_ext| ext == no_ext.as_str()),
-                None => false,
-            };
+    progress.report(ProgressEvent::Started { stage, total: mod_pack.list_conflicts().len() });
+    let results = pool::map_in_order(mod_pack.list_conflicts(), args.jobs, |conf| {
+        let result = merge_single_conflict(conf, &context);
+        progress.report(ProgressEvent::Processed { stage, path: conf.path(), mod_name: None });
+        result
+    });
+    progress.report(ProgressEvent::Finished { stage });
 
-            let vanilla_file = match vanilla_fetch(conf.path(),config,should_transcode,should_transcode) {
-                Some(contents) => contents,
-                None => {
-                    eprintln!("Error opening vanilla file for comparison: {}",conf.path().display());
-                    return Err(());
-                },
-            };
+    let (entries,conflicts): (Vec<ManifestEntry>,Vec<ConflictReport>) = results.into_iter().collect::<Result<Vec<_>,()>>()?.into_iter().unzip();
 
-            for (idx,mod_info) in conf.list_mods().iter().enumerate() {
-                if let Some(current) = mod_pack.get_mod(&mod_info) {
-                    if current.is_zip() {
-                        if let Some(contents) = mod_zip_fetch(conf.path(), current, should_transcode, should_transcode) {
-                            //println!("{}",contents);
-                            file_contents.push(contents);
-                            file_indices.push(idx);
-                        } else {
-                            eprintln!("Error unpacking file in previously registered .zip: {}",mod_info);
-                        }
-                    } else if let Some(contents) = mod_path_fetch(conf.path(), current,should_transcode,should_transcode) {
-                        //println!("{}",contents);
-                        file_contents.push(contents);
-                        file_indices.push(idx);
-                    } else {
-                        eprintln!("Error unpacking file in previously registered folder: {}",mod_info);
-                    }
-                } else {
-                    eprintln!("Was unable to unpack one of the previously read mods: {}",mod_info);
+    let override_reports = match overrides.as_mut() {
+        Some(overrides) => {
+            let reports = review_overrides(overrides, config, mod_pack, reader, progress);
+            for path in overrides.paths() {
+                let written = overrides.read(path).and_then(|contents| write_to_mod_folder(&output_root, Path::new(&args.folder_name()), &contents, path));
+                if let Err(e) = written {
+                    progress.warn(stage, &format!("Could not copy the override for {}: {}",path.display(),e));
                     return Err(());
                 }
             }
+            if let Err(e) = overrides.save_records() {
+                progress.warn(stage, &format!("Could not record the inputs of the overrides: {}",e));
+            }
+            reports
+        },
+        None => Vec::new(),
+    };
 
-            let file_content = diff_single_conflict(&vanilla_file, &file_contents, false);
+    if !entries.is_empty() {
+        let manifest = PatchManifest::new(entries);
+        let written = match manifest.to_json() {
+            Ok(json) => write_to_mod_folder(&output_root, Path::new(&args.folder_name()), json.as_bytes(), Path::new(MANIFEST_NAME)),
+            Err(e) => Err(e.into()),
+        };
+        if let Err(e) = written {
+            progress.warn(stage, &format!("Error writing the patch manifest: {}",e));
+        }
+    }
+        
+    Ok(MergeReport::new(&args.patch_name, conflicts).with_overrides(override_reports))
+}
 
-            if let Some(content) = &file_content {
-                let mod_folder = args.folder_name();
-                let mod_folder: &Path = Path::new(&mod_folder);
+/// Merges every conflict in memory without writing anything, to preview what a patch would contain
+/// Files that would merge cleanly carry a unified diff of the merged result against vanilla
+/// 
+/// #Arguments
+/// 
+/// * `config` - configuration options for our game
+/// 
+/// * `args` - options left over from arguments, will be removed soon
+/// 
+/// * `mod_pack` - the current mod load order to be merged 
+/// 
+/// * `reader` - shared reader for mod contents, so each mod archive is only opened once
+/// 
+/// * `progress` - receives an event for every conflict and every mod file read, as well as any warnings
+pub fn preview_merge(config: &ConfigOptions, args: &ArgOptions, mod_pack: &ModPack, reader: &ModReader, progress: &dyn ProgressReporter) -> Result<MergeReport,std::io::Error> {
+    let stage = ProgressStage::Merging;
+    let resolutions = open_resolutions(args, progress);
+    let mut overrides = open_overrides(config).map_err(|e| std::io::Error::other(e.to_string()))?;
+    warn_ignored_settings(config, progress);
+    progress.report(ProgressEvent::Started { stage, total: mod_pack.list_conflicts().len() });
+    let results = pool::map_in_order(mod_pack.list_conflicts(), args.jobs, |conf| {
+        let result = read_conflict(conf, config, args, mod_pack, reader, progress).map(|inputs| {
+            let mut report = inputs.report;
+            if overrides.as_ref().map(|o| o.contains(conf.path())).unwrap_or(false) {
+                report.strategy = MergeStrategy::Override;
+                report.outcome = MergeOutcome::Overridden;
+                return report;
+            }
+            let (outcome,strategy) = merge_versions(config, conf.path(), &inputs.vanilla_file, &inputs.file_contents, resolutions.as_ref());
+            report.strategy = strategy;
+            report.hunks_merged = Some(outcome.hunks_merged);
+            report.hunks_conflicted = Some(outcome.hunks_conflicted);
+            report.hunks = hunk_reports(&outcome, &version_names(conf, mod_pack, &inputs.file_indices));
+            match outcome.text {
+                Some(merged) => {
+                    if let Err(e) = encodings::encode_text(&merged, inputs.entry.output_encoding) {
+                        progress.warn(stage, &format!("Could not write the merged {}: {}",conf.path().display(),e));
+                        report.outcome = MergeOutcome::WriteFailed;
+                    }
+                    let path = conf.path().to_string_lossy().replace('\\', "/");
+                    report.diff = Some(unified_diff::unified_diff(&format!("a/{}",path), &format!("b/{}",path), &inputs.vanilla_file, &merged, DIFF_CONTEXT));
+                },
+                None => {
+                    report.strategy = MergeStrategy::Manual;
+                    report.outcome = MergeOutcome::NeedsManualMerge;
+                    report.output = PathBuf::from(args.folder_name() + "_bad");
+                },
+            }
+            report
+        });
+        progress.report(ProgressEvent::Processed { stage, path: conf.path(), mod_name: None });
+        result
+    });
+    progress.report(ProgressEvent::Finished { stage });
 
-                match write_to_mod_folder_string(mod_folder, content.clone(), conf.path(), should_transcode) {
-                    Ok(_) => successful+=1,
-                    Err(e) => eprintln!("Error with file: {} ==> {} ..with.. {}",conf.path().display(),mod_folder.display(),e),
-                };
-                continue;
-            } else {
-                eprintln!("This file will need manual merging: {}",conf.path().display());
-
-                //Process vanilla file
-                let mod_folder = args.folder_name() + "_bad";
-                let cur_folder: PathBuf = [&mod_folder,"vanilla"].iter().collect();
-                let _try_write = write_to_mod_folder_string(&cur_folder, vanilla_file, conf.path(), should_transcode);
-
-                //Process the rest of the files
-                for (file_index,file_content) in file_indices.iter().zip(file_contents) {
-                    let cur_mod = &conf.list_mods()[*file_index];
-                    let cur_mod = match mod_pack.get_mod(cur_mod) {
-                        Some(m) => m,
-                        None => return Err(()),
-                    };
-                    let cur_folder: PathBuf = [&mod_folder,cur_mod.get_name()].iter().collect();
-                    let _try_write = write_to_mod_folder_string(&cur_folder, file_content, conf.path(), should_transcode);
-                }
+    let conflicts = match results.into_iter().collect::<Result<Vec<ConflictReport>,()>>() {
+        Ok(c) => c,
+        Err(_) => return Err(std::io::Error::other("Could not read every version of the conflicting files")),
+    };
+    // Records are only updated by a real merge
+    let override_reports = match overrides.as_mut() {
+        Some(overrides) => review_overrides(overrides, config, mod_pack, reader, progress),
+        None => Vec::new(),
+    };
+    Ok(MergeReport::new(&args.patch_name, conflicts).with_overrides(override_reports))
+}
+
+/// Finds every conflicting file that neither the automatic merge nor the saved resolutions can settle
+/// Each is split into runs that merge cleanly and the hunks that still need a decision
+/// 
+/// #Arguments
+/// 
+/// * `config` - configuration options for our game
+/// 
+/// * `args` - options left over from arguments, will be removed soon
+/// 
+/// * `mod_pack` - the current mod load order to be merged 
+/// 
+/// * `reader` - shared reader for mod contents, so each mod archive is only opened once
+/// 
+/// * `resolutions` - resolutions saved so far
+/// 
+/// * `progress` - receives an event for every conflict and every mod file read, as well as any warnings
+pub fn unresolved_conflicts(config: &ConfigOptions, args: &ArgOptions, mod_pack: &ModPack, reader: &ModReader, resolutions: &ResolutionStore, progress: &dyn ProgressReporter) -> Result<Vec<UnresolvedConflict>,std::io::Error> {
+    let stage = ProgressStage::Merging;
+    progress.report(ProgressEvent::Started { stage, total: mod_pack.list_conflicts().len() });
+    let results = pool::map_in_order(mod_pack.list_conflicts(), args.jobs, |conf| {
+        let result = read_conflict(conf, config, args, mod_pack, reader, progress).map(|inputs| {
+            let (outcome,_) = merge_versions(config, conf.path(), &inputs.vanilla_file, &inputs.file_contents, Some(resolutions));
+            if outcome.text.is_some() {
+                return None;
             }
-        }
-        
-        Ok(successful)
+            Some(UnresolvedConflict {
+                path: conf.path().to_path_buf(),
+                mods: version_names(conf, mod_pack, &inputs.file_indices),
+                chunks: merge_lines(&inputs.vanilla_file, &inputs.file_contents),
+            })
+        });
+        progress.report(ProgressEvent::Processed { stage, path: conf.path(), mod_name: None });
+        result
+    });
+    progress.report(ProgressEvent::Finished { stage });
+
+    match results.into_iter().collect::<Result<Vec<Option<UnresolvedConflict>>,()>>() {
+        Ok(conflicts) => Ok(conflicts.into_iter().flatten().collect()),
+        Err(_) => Err(std::io::Error::other("Could not read every version of the conflicting files")),
+    }
+}
+
+/// Everything needed to merge a single conflict, shared by all merge workers
+struct MergeContext<'a> {
+    config: &'a ConfigOptions,
+    args: &'a ArgOptions,
+    output_root: &'a Path,
+    mod_pack: &'a ModPack,
+    reader: &'a ModReader,
+    previous: Option<&'a PreviousPatch>,
+    resolutions: Option<&'a ResolutionStore>,
+    overrides: Option<&'a OverrideDir>,
+    progress: &'a dyn ProgressReporter,
 }
 
-/// Convert a relative path to the current directory to an absolute path
-/// Can likely be deprecated
+/// Merges a single conflicting file and writes the result to the patch folder
+/// Files that could not be merged are written to the manual merge folder instead
+/// If the previous patch merged exactly the same inputs, its output is reused rather than merging again
+/// Returns the manifest entry describing the inputs of the merge, along with a report of its outcome
 /// 
 /// #Arguments
 /// 
-/// * `args` - unused command line arguments
+/// * `conf` - the conflicting file and the mods which contain it
 /// 
-/// * `path` - relative path to make absolute
-fn current_dir_path(_args: &ArgOptions, path: &Path) -> Result<PathBuf,std::io::Error> {
-    let current_dir = std::env::current_dir()?;
-    Ok(current_dir.join(path))
+/// * `context` - game configuration, program arguments and shared readers for the whole merge
+fn merge_single_conflict(conf: &ModConflict, context: &MergeContext) -> Result<(ManifestEntry,ConflictReport),()> {
+    let stage = ProgressStage::Merging;
+    let MergeContext {config, args, output_root, mod_pack, reader, previous, resolutions, overrides, progress} = *context;
+
+    let ConflictInputs {vanilla_file, file_contents, file_indices, should_transcode, mut entry, mut report} = read_conflict(conf, config, args, mod_pack, reader, progress)?;
+    let mod_folder = args.folder_name();
+    let mod_folder: &Path = Path::new(&mod_folder);
+
+    // Overrides are copied into the patch once every conflict is merged
+    if overrides.map(|o| o.contains(conf.path())).unwrap_or(false) {
+        entry.strategy = MergeStrategy::Override;
+        entry.merged = true;
+        report.strategy = MergeStrategy::Override;
+        report.outcome = MergeOutcome::Overridden;
+        return Ok((entry,report));
+    }
+
+    // Saved resolutions and overrides may have changed or been removed since, so files that used them are always merged again
+    let reusable = previous.and_then(|patch| patch.reusable_output(&entry)).filter(|(strategy,_)| !matches!(strategy, MergeStrategy::Resolved | MergeStrategy::Override));
+    if let Some((strategy,contents)) = reusable {
+        entry.strategy = strategy;
+        match write_to_mod_folder(output_root, mod_folder, &contents, conf.path()) {
+            Ok(_) => {
+                if args.review_patches {
+                    match encodings::read_bytes_to_string(contents, should_transcode, false) {
+                        Some(merged) => write_review_patch(conf, context, &vanilla_file, &file_indices, &file_contents, &merged),
+                        None => progress.warn(stage, &format!("Could not read the previous output for {}, no review patch was written",conf.path().display())),
+                    }
+                }
+                entry.merged = true;
+                report.strategy = strategy;
+                report.outcome = MergeOutcome::Reused;
+                return Ok((entry,report));
+            },
+            Err(e) => progress.warn(stage, &format!("Could not reuse the previous output for {}, merging again: {}",conf.path().display(),e)),
+        }
+    }
+
+    let (outcome,strategy) = merge_versions(config, conf.path(), &vanilla_file, &file_contents, resolutions);
+    report.hunks_merged = Some(outcome.hunks_merged);
+    report.hunks_conflicted = Some(outcome.hunks_conflicted);
+    report.hunks = hunk_reports(&outcome, &version_names(conf, mod_pack, &file_indices));
+
+    if let Some(content) = outcome.text {
+        entry.strategy = strategy;
+        report.strategy = strategy;
+        if args.review_patches {
+            write_review_patch(conf, context, &vanilla_file, &file_indices, &file_contents, &content);
+        }
+        match write_to_mod_folder_string(output_root, mod_folder, &content, conf.path(), entry.output_encoding, Some(entry.output_line_ending)) {
+            Ok(_) => entry.merged = true,
+            Err(e) => {
+                progress.warn(stage, &format!("Error with file: {} ==> {} ..with.. {}",conf.path().display(),mod_folder.display(),e));
+                report.outcome = MergeOutcome::WriteFailed;
+            },
+        }
+    } else {
+        progress.warn(stage, &format!("This file will need manual merging: {}",conf.path().display()));
+        entry.strategy = MergeStrategy::Manual;
+
+        //Process vanilla file
+        let mod_folder = args.folder_name() + "_bad";
+        report.strategy = MergeStrategy::Manual;
+        report.outcome = MergeOutcome::NeedsManualMerge;
+        report.output = PathBuf::from(&mod_folder);
+        let cur_folder: PathBuf = [&mod_folder,"vanilla"].iter().collect();
+        let _try_write = write_to_mod_folder_string(output_root, &cur_folder, &vanilla_file, conf.path(), entry.vanilla_encoding, entry.vanilla_line_ending);
+
+        //Keep every hunk that did apply, so only the refused ones need merging by hand
+        if let Some(partial) = &outcome.partial {
+            let cur_folder: PathBuf = [&mod_folder,PARTIAL_MERGE_FOLDER].iter().collect();
+            let _try_write = write_to_mod_folder_string(output_root, &cur_folder, partial, conf.path(), entry.output_encoding, Some(entry.output_line_ending));
+        }
+
+        //Process the rest of the files, each in the encoding and line ending it was read in
+        for ((file_index,file_content),input) in file_indices.iter().zip(&file_contents).zip(&entry.inputs) {
+            let cur_mod = &conf.list_mods()[*file_index];
+            let cur_mod = match mod_pack.get_mod(cur_mod) {
+                Some(m) => m,
+                None => return Err(()),
+            };
+            let cur_folder: PathBuf = [&mod_folder,cur_mod.get_name()].iter().collect();
+            let _try_write = write_to_mod_folder_string(output_root, &cur_folder, file_content, conf.path(), input.encoding, input.line_ending);
+        }
+    }
+    Ok((entry,report))
+}
+
+/// Writes `<patch>_patches/<path>.patch`, showing a merged file against vanilla and against every mod's version
+/// Failing to write it only warns, since the merged file itself is fine
+fn write_review_patch(conf: &ModConflict, context: &MergeContext, vanilla_file: &str, file_indices: &[usize], file_contents: &[String], merged: &str) {
+    let MergeContext {args, output_root, mod_pack, progress, ..} = *context;
+    let mod_names = version_names(conf, mod_pack, file_indices);
+    let mods: Vec<(&str,&str)> = mod_names.iter().map(|name| name.as_str()).zip(file_contents.iter().map(|c| c.as_str())).collect();
+    let path = conf.path().to_string_lossy().replace('\\', "/");
+    let patch = unified_diff::review_patch(&path, vanilla_file, &mods, merged, DIFF_CONTEXT);
+
+    let patch_folder = args.folder_name() + "_patches";
+    let patch_path = PathBuf::from(format!("{}.patch", conf.path().display()));
+    if let Err(e) = write_to_mod_folder_string(output_root, Path::new(&patch_folder), &patch, &patch_path, TextEncoding::Utf8, None) {
+        progress.warn(ProgressStage::Merging, &format!("Could not write the review patch for {}: {}",conf.path().display(),e));
+    }
+}
+
+// Where each hunk of the automatic merge went, naming the mod it came from
+fn hunk_reports(outcome: &DiffOutcome, names: &[String]) -> Vec<HunkReport> {
+    outcome.hunks.iter().map(|hunk| HunkReport {
+        mod_name: names.get(hunk.version).cloned().unwrap_or_default(),
+        line: hunk.line,
+        applied: hunk.applied,
+        offset: hunk.offset,
+        fuzz: hunk.fuzz,
+    }).collect()
+}
+
+// Name of the mod behind each version of a conflicting file
+fn version_names(conf: &ModConflict, mod_pack: &ModPack, file_indices: &[usize]) -> Vec<String> {
+    file_indices.iter().map(|index| {
+        let key = &conf.list_mods()[*index];
+        mod_pack.get_mod(key).map(|m| m.get_name()).unwrap_or(key).to_owned()
+    }).collect()
 }
 
-/// Write a byte buffer to a file in a mod folder
+/// Merges every version of a conflicting file, returning the strategy that produced the result
+/// When the automatic merge fails, the line based merge is tried with any saved resolutions for its clashing hunks
+fn merge_versions(config: &ConfigOptions, path: &Path, vanilla_file: &str, file_contents: &[String], resolutions: Option<&ResolutionStore>) -> (DiffOutcome,MergeStrategy) {
+    let mut outcome = diff_single_conflict_with(vanilla_file, file_contents, &config.diff_options_for(path));
+    if outcome.text.is_none() {
+        // Only files with clashing hunks, every one of them resolved before, come back from the store
+        // Files refused for missing context have nothing to resolve and stay with a manual merge
+        let chunks = merge_lines(vanilla_file, file_contents);
+        if let Some(lines) = resolutions.and_then(|store| store.apply(path, &chunks)) {
+            let line_ending = match encodings::detect_line_ending(vanilla_file) {
+                Some(LineEnding::Lf) => "\n",
+                _ => "\r\n",
+            };
+            let mut text = lines.join(line_ending);
+            if vanilla_file.ends_with('\n') {
+                text.push_str(line_ending);
+            }
+            outcome.text = Some(text);
+            return (outcome,MergeStrategy::Resolved);
+        }
+    }
+    (outcome,MergeStrategy::AutoMerge)
+}
+
+// Warns about settings of the old diff match patch merge that the config still sets but nothing reads
+fn warn_ignored_settings(config: &ConfigOptions, progress: &dyn ProgressReporter) {
+    for setting in &config.ignored_settings {
+        progress.warn(ProgressStage::Merging, &format!("{} in the config of {} no longer changes the merge and is ignored",setting,config.game_name));
+    }
+}
+
+// Reads the game's override folder, if it has one
+fn open_overrides(config: &ConfigOptions) -> Result<Option<OverrideDir>,Box<dyn std::error::Error>> {
+    match &config.override_dir {
+        Some(dir) => Ok(Some(OverrideDir::open(dir)?)),
+        None => Ok(None),
+    }
+}
+
+/// Checks every override against the current vanilla file and every enabled mod's version of it
+/// Warns about overrides whose inputs changed since they were written
+fn review_overrides(overrides: &mut OverrideDir, config: &ConfigOptions, mod_pack: &ModPack, reader: &ModReader, progress: &dyn ProgressReporter) -> Vec<OverrideReport> {
+    let paths: Vec<PathBuf> = overrides.paths().into_iter().map(Path::to_path_buf).collect();
+    let mut reports = Vec::new();
+    for path in paths {
+        // An override that cannot be read fails the merge when it is copied, so there is nothing to review
+        let override_hash = match overrides.read(&path) {
+            Ok(contents) => manifest::content_hash(&String::from_utf8_lossy(&contents)),
+            Err(_) => continue,
+        };
+        let transcode = should_transcode(&path, config);
+        let vanilla_hash = vanilla_fetch(&path, config, transcode, transcode).map(|vanilla| manifest::content_hash(&vanilla.text));
+        let mut inputs = Vec::new();
+        for status in mod_pack.load_order().iter().filter(|status| status.status()) {
+            let mod_info = match mod_pack.get_mod(status.name()) {
+                Some(mod_info) => mod_info,
+                None => continue,
+            };
+            let mod_path = match mod_info.get_filetree().iter().find(|file| Path::new(&file.to_lowercase()) == path) {
+                Some(mod_path) => mod_path,
+                None => continue,
+            };
+            match reader.read_text(mod_info, Path::new(mod_path), transcode, transcode) {
+                Ok(contents) => inputs.push(ManifestInput {mod_name: status.name().to_owned(), mod_version: mod_info.get_version().clone(), hash: manifest::content_hash(&contents.text), encoding: contents.encoding, line_ending: contents.line_ending}),
+                Err(e) => progress.warn(ProgressStage::Merging, &format!("Error reading {} from {}: {}",mod_path,mod_info.get_name(),e)),
+            }
+        }
+
+        let report = overrides.review(&path, OverrideRecord {override_hash, vanilla_hash, inputs});
+        if report.stale {
+            progress.warn(ProgressStage::Merging, &format!("The override for {} was written against older versions of: {}",path.display(),report.changed.join(", ")));
+        }
+        reports.push(report);
+    }
+    reports
+}
+
+/// Encoding a merged file is written in, the one configured for its folder or else that of the vanilla file
+/// Plain ASCII reads as UTF-8, so a transcoded vanilla file holding nothing else keeps the game's single byte encoding
+fn output_encoding(path: &Path, config: &ConfigOptions, vanilla_encoding: TextEncoding, vanilla_file: &str, transcode: bool) -> TextEncoding {
+    if let Some(encoding) = config.encoding_for(path) {
+        return encoding;
+    }
+    match vanilla_encoding {
+        TextEncoding::Utf8 if transcode && vanilla_file.is_ascii() => TextEncoding::Windows1252,
+        encoding => encoding,
+    }
+}
+
+// Text files are converted to and from the game's encoding, unless their extension is listed in no_transcode
+fn should_transcode(path: &Path, config: &ConfigOptions) -> bool {
+    match path.extension() {
+        Some(ext) => !config.no_transcode.iter().any(|no_ext| ext == no_ext.as_str()),
+        None => false,
+    }
+}
+
+// Reads the saved resolutions named in the arguments, merging without them if they cannot be read
+fn open_resolutions(args: &ArgOptions, progress: &dyn ProgressReporter) -> Option<ResolutionStore> {
+    let path = args.resolutions.as_ref()?;
+    match ResolutionStore::open(path) {
+        Ok(store) => Some(store),
+        Err(e) => {
+            progress.warn(ProgressStage::Merging, &format!("Could not read the saved resolutions in {}: {}",path.display(),e));
+            None
+        },
+    }
+}
+
+/// Every version of a conflicting file, along with the records describing it
+struct ConflictInputs {
+    vanilla_file: String,
+    file_contents: Vec<String>,
+    /// Index in the conflict's mod list of each entry in `file_contents`
+    file_indices: Vec<usize>,
+    should_transcode: bool,
+    entry: ManifestEntry,
+    report: ConflictReport,
+}
+
+/// Reads the vanilla file and each mod's version of a conflict
+/// The report starts out as a successful automatic merge into the patch folder
+fn read_conflict(conf: &ModConflict, config: &ConfigOptions, args: &ArgOptions, mod_pack: &ModPack, reader: &ModReader, progress: &dyn ProgressReporter) -> Result<ConflictInputs,()> {
+    let stage = ProgressStage::Merging;
+    progress.report(ProgressEvent::Conflict { path: conf.path(), mods: conf.list_mods() });
+    let mut file_contents: Vec<String> = Vec::new();
+    let mut file_indices: Vec<usize> = Vec::new();
+    let mut inputs: Vec<ManifestInput> = Vec::new();
+    let should_transcode = should_transcode(conf.path(), config);
+
+    let DecodedText {text: vanilla_file, encoding: vanilla_encoding, line_ending: vanilla_line_ending} = match vanilla_fetch(conf.path(),config,should_transcode,should_transcode) {
+        Some(contents) => contents,
+        None => {
+            progress.warn(stage, &format!("Error opening vanilla file for comparison: {}",conf.path().display()));
+            return Err(());
+        },
+    };
+
+    for (idx,mod_info) in conf.list_mods().iter().enumerate() {
+        if let Some(current) = mod_pack.get_mod(mod_info) {
+            match reader.read_text(current, conf.path(), should_transcode, should_transcode) {
+                Ok(DecodedText {text: contents, encoding, line_ending}) => {
+                    inputs.push(ManifestInput {mod_name: mod_info.clone(), mod_version: current.get_version().clone(), hash: manifest::content_hash(&contents), encoding, line_ending});
+                    file_contents.push(contents);
+                    file_indices.push(idx);
+                },
+                Err(e) if current.is_zip() => progress.warn(stage, &format!("Error unpacking file in previously registered .zip: {}: {}",mod_info,e)),
+                Err(e) => progress.warn(stage, &format!("Error unpacking file in previously registered folder: {}: {}",mod_info,e)),
+            }
+        } else {
+            progress.warn(stage, &format!("Was unable to unpack one of the previously read mods: {}",mod_info));
+            return Err(());
+        }
+    }
+
+    let entry = ManifestEntry {
+        path: conf.path().to_path_buf(),
+        vanilla_hash: manifest::content_hash(&vanilla_file),
+        vanilla_encoding,
+        vanilla_line_ending,
+        inputs,
+        output_encoding: output_encoding(conf.path(), config, vanilla_encoding, &vanilla_file, should_transcode),
+        output_line_ending: config.line_ending.or(vanilla_line_ending).unwrap_or_default(),
+        diff_options: config.diff_options_for(conf.path()),
+        strategy: MergeStrategy::AutoMerge,
+        merged: false,
+    };
+    let report = ConflictReport {
+        path: conf.path().to_path_buf(),
+        mods: conf.list_mods().to_vec(),
+        strategy: MergeStrategy::AutoMerge,
+        outcome: MergeOutcome::Merged,
+        hunks_merged: None,
+        hunks_conflicted: None,
+        output: Path::new(&args.folder_name()).join(conf.path()),
+        diff: None,
+        hunks: Vec::new(),
+    };
+    Ok(ConflictInputs {vanilla_file, file_contents, file_indices, should_transcode, entry, report})
+}
+
+
+/// Write a byte buffer to a file in a mod folder, exactly as given
 /// 
 /// #Arguments
 /// 
+/// * `output_root` - directory the mod folder is created in
+/// 
 /// * `mod_folder` - mod parent directory, typically for merged mod
 /// 
 /// * `contents` - bytes to write into the file
 /// 
 /// * `path` - relative file path in the parent directory
-/// 
-/// * `encode` - if yes, encode in WINDOWS-1252, otherwise write as-is
-fn write_to_mod_folder(mod_folder: &Path, contents: &[u8], path: &Path, _encode: bool) -> Result<(),std::io::Error> {
-    let full_path = files::relative_folder_path(mod_folder, &path)?;
+fn write_to_mod_folder(output_root: &Path, mod_folder: &Path, contents: &[u8], path: &Path) -> Result<(),std::io::Error> {
+    let full_path = files::relative_folder_path(output_root, mod_folder, path);
     files::write_file_with_content(&full_path, contents)
 }
 
@@ -373,55 +858,147 @@ fn write_to_mod_folder(mod_folder: &Path, contents: &[u8], path: &Path, _encode:
 /// 
 /// #Arguments
 /// 
+/// * `output_root` - directory the mod folder is created in
+/// 
 /// * `mod_folder` - mod parent directory, typically for merged mod
 /// 
 /// * `contents` - string to write into the file
 /// 
 /// * `path` - relative file path in the parent directory
 /// 
-/// * `encode` - if yes, encode in WINDOWS-1252, otherwise write as-is
-fn write_to_mod_folder_string(mod_folder: &Path, contents: String, path: &Path, encode: bool) -> Result<(),std::io::Error> {
-    let full_path = files::relative_folder_path(mod_folder, &path)?;
-    files::write_file_with_string(&full_path, contents, encode)
+/// * `encoding` - encoding to write the file in, failing if any character cannot be written in it
+/// 
+/// * `line_ending` - line ending to end every line with, None to write the lines as they are
+fn write_to_mod_folder_string(output_root: &Path, mod_folder: &Path, contents: &str, path: &Path, encoding: TextEncoding, line_ending: Option<LineEnding>) -> Result<(),std::io::Error> {
+    let full_path = files::relative_folder_path(output_root, mod_folder, path);
+    match line_ending {
+        Some(line_ending) => files::write_file_with_encoding(&full_path, &encodings::convert_line_endings(contents, line_ending), encoding),
+        None => files::write_file_with_encoding(&full_path, contents, encoding),
+    }
 }
 
-/// Write a set of files and their byte contents to a new zip file, will overwrite an existing file
+/// Generates the complete patch, including extracted files if requested, its packaging and its descriptor
+/// Everything is written into a staging directory first and only swapped into the output directory if the whole run succeeds
+/// Any previous patch is kept as a backup, which `restore_patch_backup` puts back
+/// Returns a report of how every conflict was merged
 /// 
 /// #Arguments
 /// 
-/// * `mod_folder` - parent directory for zip file
+/// * `config` - configuration options for our game
 /// 
-/// * `staged_data` - list of file names and associated data
+/// * `args` - Program arguments, includes name of mod, output directory and patch format
 /// 
-/// * `zip` - zip filename
-fn write_to_mod_zip(mod_folder: &Path, staged_data: HashMap<String,Vec<u8>>, zip: &Path) -> Result<(),std::io::Error> {
-    let zip_path = files::relative_folder_path(mod_folder, zip)?;
-    zips::zip_write_files(&zip_path,staged_data)
-    
+/// * `mod_pack` - the current mod load order to be merged
+/// 
+/// * `reader` - shared reader for mod contents, so each mod archive is only opened once
+/// 
+/// * `progress` - receives progress events and warnings
+pub fn generate_patch(config: &ConfigOptions, args: &ArgOptions, mod_pack: &ModPack, reader: &ModReader, progress: &dyn ProgressReporter) -> Result<MergeReport,std::io::Error> {
+    let output_root = args.output_root()?;
+    let staging_dir = output_root.join(format!(".{}.staging",args.folder_name()));
+    let backup_dir = output_root.join(format!(".{}.backup",args.folder_name()));
+
+    staging::discard(&staging_dir)?;
+    fs::create_dir_all(&staging_dir)?;
+    let staged_args = args.clone().with_output_dir(staging_dir.clone());
+    let previous = if args.incremental {
+        PreviousPatch::open(&output_root, &args.folder_name())
+    } else {
+        None
+    };
+
+    let staged = stage_patch(config, &staged_args, &staging_dir, mod_pack, reader, previous.as_ref(), progress);
+    // Release any handle on the previous patch before it is moved into the backup
+    drop(previous);
+    match staged {
+        Ok(report) => {
+            staging::swap_into_place(&staging_dir, &output_root, &args.patch_entries(), &backup_dir)?;
+            Ok(report)
+        },
+        Err(e) => {
+            let _ = staging::discard(&staging_dir);
+            Err(e)
+        },
+    }
+}
+
+/// Writes every part of the patch into the staging directory
+fn stage_patch(config: &ConfigOptions, staged_args: &ArgOptions, staging_dir: &Path, mod_pack: &ModPack, reader: &ModReader, previous: Option<&PreviousPatch>, progress: &dyn ProgressReporter) -> Result<MergeReport,std::io::Error> {
+    if staged_args.extract {
+        extract_all_files(mod_pack, staged_args, config, false, staging_dir, reader, progress);
+    }
+    let report = match auto_merge(config, staged_args, mod_pack, reader, previous, open_resolutions(staged_args, progress).as_ref(), progress) {
+        Ok(r) => r,
+        Err(_) => return Err(std::io::Error::other("Merging failed, the previous patch was left untouched")),
+    };
+    package_patch(staged_args)?;
+    write_mod_desc_to_folder(staged_args, mod_pack)?;
+    Ok(report)
+}
+
+/// Puts the patch that was replaced by the last successful `generate_patch` back in place
+/// Returns false if there was no backup to restore
+/// 
+/// #Arguments
+/// 
+/// * `args` - Program arguments, includes name of mod and output directory
+pub fn restore_patch_backup(args: &ArgOptions) -> Result<bool,std::io::Error> {
+    let output_root = args.output_root()?;
+    let backup_dir = output_root.join(format!(".{}.backup",args.folder_name()));
+    staging::restore_backup(&output_root, &args.patch_entries(), &backup_dir)
+}
+
+/// Packages the generated patch into the layout its descriptor points at
+/// Zip patches have their merged folder compressed into a single archive, and an empty patch is still created so the descriptor is never left dangling
+/// 
+/// # Arguments
+/// 
+/// * `args` - Program arguments, includes name of mod, output directory and patch format
+pub fn package_patch(args: &ArgOptions) -> Result<(),std::io::Error> {
+    let output_root = args.output_root()?;
+    let patch_folder = output_root.join(args.folder_name());
+
+    match args.patch_format {
+        PatchFormat::Folder => fs::create_dir_all(&patch_folder),
+        PatchFormat::Zip => {
+            let zip_path = output_root.join(args.folder_name() + ".zip");
+            let mut zip_output = zips::ZipStreamWriter::create(&zip_path)?;
+            let mut entries = files::walk_in_dir(&patch_folder, Some(&patch_folder));
+            entries.sort();
+            for entry in entries {
+                let entry_name = entry.to_string_lossy();
+                if zip_output.start_entry(&entry_name)? {
+                    let mut file = File::open(patch_folder.join(&entry))?;
+                    std::io::copy(&mut file, &mut zip_output)?;
+                }
+            }
+            zip_output.finish()?;
+
+            if patch_folder.is_dir() {
+                fs::remove_dir_all(&patch_folder)?;
+            }
+            Ok(())
+        },
+    }
 }
 
 /// Generates and writes a .mod file for the modpack at the designated location.
 /// Uses the dependencies of all conflicting mods, as well as replacement paths and user directories
-/// Takes name from Arg Options
+/// Takes name from Arg Options, and points at a folder or zip file depending on the patch format
 /// 
 /// # Arguments
 /// 
 /// * `args` - Program arguments, includes name of mod, data locations, etc.
 /// 
 /// * `mod_pack` - information on all loaded mods, includes conflicting files, enabled mods, etc.
-
 pub fn write_mod_desc_to_folder(args: &ArgOptions, mod_pack: &ModPack) -> Result<(),std::io::Error> {
-    let mut mod_file_name = PathBuf::from(args.folder_name());
-    mod_file_name.set_extension("mod");
+    let mod_file_name = PathBuf::from(args.folder_name() + ".mod");
 
-    let full_path = if args.dry_run || mod_pack.list_conflicts().is_empty() {
-        current_dir_path(args, &mod_file_name)?
-    } else {
-        files::relative_folder_path(Path::new(&args.folder_name()), &mod_file_name)?
-    };
+    // The descriptor sits beside the patch, the same way the launcher expects it in the mod directory
+    let full_path = args.output_root()?.join(&mod_file_name);
 
-    //Write the header of the mod file with name and archive
-    let mut file_contents = format!("name = \"{}\"\narchive = \"mod/{}.zip\"\n", args.patch_name, args.folder_name());
+    //Write the header of the mod file with name and patch location
+    let mut file_contents = format!("name = \"{}\"\n{}\n", args.patch_name, args.descriptor_entry());
 
     if args.extract {
         let mod_user_dirs = mod_pack.list_user_dirs();
@@ -462,66 +1039,6 @@ pub fn write_mod_desc_to_folder(args: &ArgOptions, mod_pack: &ModPack) -> Result
     Ok(())
 }
 
-/// Wrapper Function for zip_fetch_file_relative that fetches files from a zip folder relative to the mod directory
-/// 
-/// # Arguments
-/// 
-/// * `dir` - Path of the file in the mod zip archive we are fetching
-/// 
-/// * `mod_entry` - Mod information to use for determining which zip archive
-/// 
-/// * `decode` - If yes, decode the file from Windows-1252 into Unicode
-/// 
-/// * `normalize` - If yes, to convert all line endings into Windows style line endings
-/// 
-fn mod_zip_fetch(dir: &Path, mod_entry: &ModInfo, decode: bool, normalize: bool) -> Option<String> {
-        if !mod_entry.is_zip() {
-            return None;
-        }
-        let zip_archive = mod_entry.get_data_path();
-        zips::zip_fetch_file_relative(dir,zip_archive,decode,normalize)
-}
-
-/// Produce a map of all relative file paths in a mod file directory and their contents
-/// #Arguments
-/// 
-/// * `mod_entry` - the mod to read
-fn mod_path_fetch_all(mod_entry: &ModInfo) -> HashMap<String,Vec<u8>> {
-    if mod_entry.is_zip() {
-        HashMap::new()
-    } else {
-        files::fetch_all_files_in_path(mod_entry.get_data_path())
-    }
-}
-
-/// Produce a map of all relative file paths in a mod zip file and their contents
-/// #Arguments
-/// 
-/// * `mod_entry` - the mod to read
-fn mod_zip_fetch_all(mod_entry: &ModInfo) -> HashMap<String,Vec<u8>> {
-    if !mod_entry.is_zip() {
-        HashMap::new()
-    } else {
-        zips::zip_fetch_all_files(mod_entry.get_data_path())
-    }
-    
-}
-
-/// Get the contents of a single file in a mod file directory or zip file
-/// #Arguments
-/// 
-/// * `dir` - file to extact data from
-/// 
-/// * `mod_entry` - mod to extract from
-/// 
-/// * `decode` - if yes, attempt to decode the file contents, otherwise read in bytes as-is
-/// 
-/// * `normalize` - if yes, convert all line-endings to windows-style
-fn mod_path_fetch(dir: &Path, mod_entry: &ModInfo, decode: bool, normalize: bool) -> Option<String> {
-        let full_path: PathBuf = [mod_entry.get_data_path(),dir].iter().collect();
-        files::fetch_file_in_path(&full_path,decode,normalize)
-}
-
 /// Get the contents of a single file in a vanilla file directory
 /// #Arguments
 /// 
@@ -532,9 +1049,9 @@ fn mod_path_fetch(dir: &Path, mod_entry: &ModInfo, decode: bool, normalize: bool
 /// * `decode` - if yes, attempt to decode the file contents, otherwise read in bytes as-is
 /// 
 /// * `normalize` - if yes, convert all line-endings to windows-style
-fn vanilla_fetch(dir: &Path, config: &ConfigOptions, decode: bool, normalize: bool) -> Option<String> {
+fn vanilla_fetch(dir: &Path, config: &ConfigOptions, decode: bool, normalize: bool) -> Option<DecodedText> {
         let full_path: PathBuf = config.data_path.join(dir);
-        files::fetch_file_in_path(&full_path,decode,normalize)
+        files::fetch_text_in_path(&full_path,decode,normalize)
 }
 
 /// Extract all files from all currently enabled mods into the output mod directory
@@ -546,54 +1063,358 @@ fn vanilla_fetch(dir: &Path, config: &ConfigOptions, decode: bool, normalize: bo
 /// 
 /// * `config` - information about the game files
 /// 
-/// * `to_zip` - if yes, compress output to zip file, uses a lot of memory as all data is written to disk at once
-pub fn extract_all_files(mods: &ModPack, args: &ArgOptions, _config: &ConfigOptions, to_zip: bool, destination: &Path) {
-    let mod_folder_buf = destination.join(args.folder_name());
-    let mod_folder = mod_folder_buf.as_path();
+/// * `to_zip` - if yes, write all files into a single compressed zip file, one file at a time, instead of a folder
+/// 
+/// * `destination` - directory the output mod folder or zip file is created in
+/// 
+/// * `reader` - shared reader for mod contents, so each mod archive is only opened once
+/// 
+/// * `progress` - receives an event for every file extracted, as well as any warnings
+pub fn extract_all_files(mods: &ModPack, args: &ArgOptions, _config: &ConfigOptions, to_zip: bool, destination: &Path, reader: &ModReader, progress: &dyn ProgressReporter) {
+    let stage = ProgressStage::Extracting;
+    let mod_folder_name = args.folder_name();
+    let mod_folder = Path::new(&mod_folder_name);
+    let enabled_mods: Vec<&ModInfo> = mods.load_order().iter().filter(|mod_idx| mod_idx.status()).filter_map(|mod_idx| {
+        let mod_info = mods.get_mod(mod_idx.name());
+        if mod_info.is_none() {
+            progress.warn(stage, &format!("Error looking up previously registered mod: {}", mod_idx.name()));
+        }
+        mod_info
+    }).collect();
+    let total = enabled_mods.iter().map(|mod_info| mod_info.get_filetree().len()).sum();
+
+    progress.report(ProgressEvent::Started { stage, total });
     if to_zip {
-        let zip_target = args.folder_name();
-        let zip_target: PathBuf = [&zip_target,".zip"].iter().collect();
-        let mut staged_zip_data = HashMap::new();
-        for mod_idx in mods.load_order() {
-            if mod_idx.status() {
-            let mod_info = match mods.get_mod(mod_idx.name()) {
-                Some(m) => m,
-                None => {eprintln!("Error looking up previously registered mod: {}", mod_idx.name()); continue},
-            };
-                if mod_info.is_zip() {
-                    let files = mod_zip_fetch_all(&mod_info);
-                    for (file_path,file_data) in files {
-                        let _old_data = staged_zip_data.insert(file_path, file_data);
+        let zip_target = destination.join(args.folder_name() + ".zip");
+        if let Err(e) = extract_to_zip(&enabled_mods, &zip_target, reader, progress) {
+            progress.warn(stage, &format!("Error writing {}: {}",zip_target.display(),e));
+        }
+    } else {
+        for mod_info in enabled_mods {
+            if mod_info.is_zip() {
+                let file_paths = match reader.list_files(mod_info) {
+                    Ok(file_paths) => file_paths,
+                    Err(e) => {
+                        progress.warn(stage, &format!("Error opening {}: {}",mod_info.get_name(),e));
+                        continue;
+                    },
+                };
+                for file_path in file_paths {
+                    let file_path = Path::new(&file_path);
+                    let written = reader.read_bytes(mod_info, file_path)
+                        .and_then(|file_data| write_to_mod_folder(destination, mod_folder, &file_data, file_path));
+                    if let Err(e) = written {
+                        progress.warn(stage, &format!("Error extracting {} from {}: {}",file_path.display(),mod_info.get_name(),e));
                     }
-                } else {
-                    let files = mod_path_fetch_all(&mod_info);
-                    for (file_path,file_data) in files {
-                        let _old_data = staged_zip_data.insert(file_path, file_data);
+                    progress.report(ProgressEvent::Processed { stage, path: file_path, mod_name: Some(mod_info.get_name()) });
+                }
+            } else {
+                for file_path in mod_info.get_filetree() {
+                    let file_path = Path::new(file_path);
+                    if let Err(e) = files::copy_file(mod_info.get_data_path().join(file_path), files::relative_folder_path(destination, mod_folder, file_path)) {
+                        progress.warn(stage, &format!("Error copying {} from {}: {}",file_path.display(),mod_info.get_name(),e));
                     }
+                    progress.report(ProgressEvent::Processed { stage, path: file_path, mod_name: Some(mod_info.get_name()) });
                 }
             }
         }
-        let _result = write_to_mod_zip(mod_folder, staged_zip_data, &zip_target);
-    } else {
-        for mod_idx in mods.load_order() {
-            if mod_idx.status() {
-            let mod_info = match mods.get_mod(mod_idx.name()) {
-                Some(m) => m,
-                None => {eprintln!("Error looking up previously registered mod: {}", mod_idx.name()); continue},
-            };
-                if mod_info.is_zip() {
-                    let files = mod_zip_fetch_all(&mod_info);
-                    for (file_path,file_data) in files {
-                        let _result = write_to_mod_folder(mod_folder, &file_data, Path::new(&file_path),true);
-                    }
-                } else {
-                    let _res = files::copy_directory_tree(&mod_info.get_data_path() , &mod_folder, true, true);
-                    //let files = mod_path_fetch_all(&mod_info);
-                    //for (file_path,file_data) in files {
-                    //    let result = write_to_mod_folder(mod_folder, &file_data, Path::new(&file_path),true);
-                    //}
+    }
+    progress.report(ProgressEvent::Finished { stage });
+}
+
+/// Write every file of the given mods into a new zip archive, holding no more than one file in memory at a time
+/// Mods later in the load order take precedence, so they are written first and any files they shadow are skipped
+/// #Arguments
+/// 
+/// * `enabled_mods` - mods to extract, in load order
+/// 
+/// * `zip_target` - path of the zip file to create
+/// 
+/// * `reader` - shared reader for mod contents
+/// 
+/// * `progress` - receives an event for every file extracted, as well as any warnings
+fn extract_to_zip(enabled_mods: &[&ModInfo], zip_target: &Path, reader: &ModReader, progress: &dyn ProgressReporter) -> Result<(),std::io::Error> {
+    let stage = ProgressStage::Extracting;
+    let mut zip_output = zips::ZipStreamWriter::create(zip_target)?;
+
+    for mod_info in enabled_mods.iter().rev() {
+        let file_paths = match reader.list_files(mod_info) {
+            Ok(file_paths) => file_paths,
+            Err(e) => {
+                progress.warn(stage, &format!("Error opening {}: {}",mod_info.get_name(),e));
+                continue;
+            },
+        };
+        for file_path in file_paths {
+            // The entry is only started once the whole file has been read, so a file that can't be read leaves its name to the mods it shadows
+            if !zip_output.contains(&file_path) {
+                match reader.read_bytes(mod_info, Path::new(&file_path)) {
+                    Ok(file_data) => {
+                        zip_output.start_entry(&file_path)?;
+                        zip_output.write_all(&file_data)?;
+                    },
+                    Err(e) => progress.warn(stage, &format!("Error extracting {} from {}: {}",file_path,mod_info.get_name(),e)),
                 }
+            }
+            progress.report(ProgressEvent::Processed { stage, path: Path::new(&file_path), mod_name: Some(mod_info.get_name()) });
+        }
+    }
+
+    zip_output.finish()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::BTreeMap;
+    use crate::test_support::{zip_entries,FakeGame};
+
+    const VANILLA_EVENTS: &str = "namespace = test\r\nevent_a = {\r\n\tweight = 1\r\n}\r\nevent_b = {\r\n\tweight = 1\r\n}\r\nevent_c = {\r\n\tweight = 1\r\n}\r\n";
+
+    // Reads the mods and finds the conflicts between the enabled ones the same way the command line does
+    fn load_mod_pack(config: &ConfigOptions) -> ModPack {
+        let mut mod_pack = ModPack::default().restrict_paths(&config.valid_paths).restrict_extensions(&config.valid_extensions);
+        let mod_list = generate_enabled_mod_list(&config.mod_path, config.new_launcher, &SilentProgress);
+        let vanilla = files_in_vanilla(config);
+        let vanilla: Vec<&Path> = vanilla.iter().map(|path| path.as_path()).collect();
+        mod_pack.register_vanilla(&vanilla);
+        mod_pack.add_mods(&mod_list, true, true, &SilentProgress);
+        mod_pack
+    }
+
+    fn two_event_mods(name: &str, new_launcher: bool) -> FakeGame {
+        FakeGame::new(name, new_launcher)
+            .vanilla("events/test.txt", VANILLA_EVENTS)
+            .vanilla("common/traits.txt", "trait = {}\r\n")
+            .folder_mod("Alpha", &[("events/test.txt", &VANILLA_EVENTS.replacen("weight = 1", "weight = 5", 1)), ("common/alpha.txt", "alpha = yes\r\n")])
+            .zip_mod("Beta", &[("events/test.txt", &VANILLA_EVENTS.replace("event_c = {\r\n\tweight = 1", "event_c = {\r\n\tweight = 9")), ("common/beta.txt", "beta = yes\r\n")])
+    }
+
+    #[test]
+    fn test_scan_folder_and_zip_mods() {
+        for &new_launcher in &[false,true] {
+            let game = two_event_mods(if new_launcher {"scan-json"} else {"scan-settings"}, new_launcher)
+                .folder_mod("Gamma", &[("events/test.txt", "gamma\r\n")])
+                .enable(&["Alpha","Beta"]);
+
+            let mut all_mods = generate_entire_mod_list(&game.user_dir(), new_launcher, &SilentProgress);
+            all_mods.sort_by(|a,b| a.get_name().cmp(b.get_name()));
+            let scanned: Vec<(&str,bool,bool)> = all_mods.iter().map(|m| (m.get_name(),m.is_zip(),m.get_active())).collect();
+            assert_eq!(scanned, vec![("Alpha",false,true),("Beta",true,true),("Gamma",false,false)]);
+            let mut beta_files: Vec<&String> = all_mods[1].get_filetree().iter().collect();
+            beta_files.sort();
+            assert_eq!(beta_files, vec!["common/beta.txt","events/test.txt"]);
+
+            let enabled: Vec<String> = generate_enabled_mod_list(&game.user_dir(), new_launcher, &SilentProgress).iter().map(|m| m.get_name().to_owned()).collect();
+            assert_eq!(enabled, vec!["Alpha","Beta"]);
+
+            // Only files of enabled mods that vanilla also has can conflict
+            let mod_pack = load_mod_pack(&game.config(&["events","common"]));
+            let conflicts: Vec<(&Path,&[String])> = mod_pack.list_conflicts().iter().map(|c| (c.path(),c.list_mods())).collect();
+            assert_eq!(conflicts, vec![(Path::new("events/test.txt"),&["Alpha".to_owned(),"Beta".to_owned()][..])]);
         }
     }
+
+    #[test]
+    fn test_merge_and_package_patch() {
+        let game = two_event_mods("merge", true).enable(&["Alpha","Beta"]);
+        let config = game.config(&["events","common"]);
+        let args = game.args("Test Patch", false);
+        let mod_pack = load_mod_pack(&config);
+
+        let report = generate_patch(&config, &args, &mod_pack, &ModReader::new(), &SilentProgress).unwrap();
+        assert_eq!(report.conflicts.len(), 1);
+        assert_eq!((report.conflicts[0].outcome,report.conflicts[0].hunks_merged,report.conflicts[0].hunks_conflicted), (MergeOutcome::Merged,Some(2),Some(0)));
+
+        let merged = VANILLA_EVENTS.replacen("weight = 1", "weight = 5", 1).replace("event_c = {\r\n\tweight = 1", "event_c = {\r\n\tweight = 9");
+        assert_eq!(game.output("test patch/events/test.txt").unwrap(), merged, "The merged file keeps the line endings of vanilla");
+        // Files only one mod changes stay with that mod
+        assert_eq!(game.output("test patch/common/alpha.txt"), None);
+
+        let manifest = PatchManifest::from_json(&game.output(&format!("test patch/{}",MANIFEST_NAME)).unwrap()).unwrap();
+        let entry = manifest.find(Path::new("events/test.txt")).unwrap();
+        assert!(entry.merged);
+        assert_eq!(entry.inputs.iter().map(|input| input.mod_name.as_str()).collect::<Vec<_>>(), vec!["Alpha","Beta"]);
+
+        let descriptor = game.output("test patch.mod").unwrap();
+        assert!(descriptor.starts_with("name = \"Test Patch\"\npath = \"mod/test patch\"\n"));
+        assert!(descriptor.contains("\"\\\"Alpha\\\"\"\n\"\\\"Beta\\\"\"\n"));
+        assert_eq!(game.output("test patch_bad/vanilla/events/test.txt"), None);
+    }
+
+    #[test]
+    fn test_clashing_mods_need_manual_merge() {
+        let game = two_event_mods("clash", false)
+            .folder_mod("Delta", &[("events/test.txt", &VANILLA_EVENTS.replacen("weight = 1", "weight = 7", 1))])
+            .enable(&["Alpha","Beta","Delta"]);
+        let config = game.config(&["events"]);
+        let args = game.args("Clash", false);
+        let mod_pack = load_mod_pack(&config);
+
+        let report = auto_merge(&config, &args, &mod_pack, &ModReader::new(), None, None, &SilentProgress).unwrap();
+        let conflict = &report.conflicts[0];
+        assert_eq!((conflict.outcome,conflict.strategy,conflict.hunks_conflicted), (MergeOutcome::NeedsManualMerge,MergeStrategy::Manual,Some(1)));
+        let refused: Vec<(&str,usize)> = conflict.hunks.iter().filter(|hunk| !hunk.applied).map(|hunk| (hunk.mod_name.as_str(),hunk.line)).collect();
+        assert_eq!(refused, vec![("Delta",3)]);
+
+        // Every version is kept for manual merging, along with every hunk that did apply
+        assert_eq!(game.output("clash/events/test.txt"), None);
+        assert_eq!(game.output("clash_bad/vanilla/events/test.txt").unwrap(), VANILLA_EVENTS);
+        assert!(game.output("clash_bad/Delta/events/test.txt").unwrap().contains("weight = 7"));
+        let partial = game.output(&format!("clash_bad/{}/events/test.txt",PARTIAL_MERGE_FOLDER)).unwrap();
+        assert!(partial.contains("weight = 5") && partial.contains("weight = 9") && !partial.contains("weight = 7"));
+    }
+
+    #[test]
+    fn test_saved_resolutions_only_settle_clashing_hunks() {
+        let game = two_event_mods("resolved", false)
+            .folder_mod("Delta", &[("events/test.txt", &VANILLA_EVENTS.replacen("weight = 1", "weight = 7", 1))])
+            .enable(&["Alpha","Beta","Delta"]);
+        let config = game.config(&["events"]);
+        let args = game.args("Resolved", false);
+        let mod_pack = load_mod_pack(&config);
+        let reader = ModReader::new();
+
+        let mut store = ResolutionStore::default();
+        let conflicts = unresolved_conflicts(&config, &args, &mod_pack, &reader, &store, &SilentProgress).unwrap();
+        let hunk = store.unresolved(&conflicts[0].path, &conflicts[0].chunks)[0].clone();
+        store.insert(&conflicts[0].path, &hunk, ResolutionPolicy::LastMod.resolve(&hunk, &conflicts[0].mods));
+
+        let report = auto_merge(&config, &args, &mod_pack, &reader, None, Some(&store), &SilentProgress).unwrap();
+        assert_eq!((report.conflicts[0].outcome,report.conflicts[0].strategy), (MergeOutcome::Merged,MergeStrategy::Resolved));
+        let expected = VANILLA_EVENTS.replacen("weight = 1", "weight = 7", 1).replace("event_c = {\r\n\tweight = 1", "event_c = {\r\n\tweight = 9");
+        assert_eq!(game.output("resolved/events/test.txt").unwrap(), expected, "The resolved file keeps the line endings of vanilla");
+    }
+
+    #[test]
+    fn test_refused_file_without_clashes_is_not_resolved() {
+        // Gamma changes the line right after the block Alpha rewrote, which leaves no context to place it by
+        let lines: Vec<String> = (1..=12).map(|i| format!("key_{} = {}",i,i)).collect();
+        let mut rewrite_block = lines.clone();
+        for line in rewrite_block.iter_mut().take(8).skip(4) {
+            *line = line.replace("key", "other");
+        }
+        let mut next_line = lines.clone();
+        next_line[8] = "key_9 = changed".to_owned();
+        let game = FakeGame::new("refused", false)
+            .vanilla("common/keys.txt", &(lines.join("\r\n") + "\r\n"))
+            .folder_mod("Alpha", &[("common/keys.txt", &(rewrite_block.join("\r\n") + "\r\n"))])
+            .folder_mod("Gamma", &[("common/keys.txt", &(next_line.join("\r\n") + "\r\n"))])
+            .enable(&["Alpha","Gamma"]);
+        let config = game.config(&["common"]);
+        let args = game.args("Refused", false);
+        let mod_pack = load_mod_pack(&config);
+
+        // An empty store has nothing to settle the file with, so it is left for a manual merge as it would be without one
+        let report = auto_merge(&config, &args, &mod_pack, &ModReader::new(), None, Some(&ResolutionStore::default()), &SilentProgress).unwrap();
+        assert_eq!((report.conflicts[0].outcome,report.conflicts[0].strategy), (MergeOutcome::NeedsManualMerge,MergeStrategy::Manual));
+        assert_eq!(game.output("refused/common/keys.txt"), None);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_zip_patch_with_extracted_files() {
+        let game = two_event_mods("zip-patch", true).enable(&["Alpha","Beta"]);
+        let config = game.config(&["events","common"]);
+        let args = game.args("Zipped", true).with_patch_format(PatchFormat::Zip);
+        let mod_pack = load_mod_pack(&config);
+
+        generate_patch(&config, &args, &mod_pack, &ModReader::new(), &SilentProgress).unwrap();
+        assert!(!game.output_dir().join("zipped").exists());
+        let entries = zip_entries(&game.output_dir().join("zipped.zip"));
+        let names: Vec<&str> = entries.iter().map(|(name,_)| name.as_str()).collect();
+        assert_eq!(names, vec!["common/alpha.txt","common/beta.txt","events/test.txt",MANIFEST_NAME]);
+        // The merged file replaces either mod's own version
+        let merged = &entries[2].1;
+        assert!(merged.contains("weight = 5") && merged.contains("weight = 9"));
+        assert!(game.output("zipped.mod").unwrap().contains("archive = \"mod/zipped.zip\""));
+    }
+
+    #[test]
+    fn test_removed_override_is_merged_again() {
+        let game = two_event_mods("override-removed", true).enable(&["Alpha","Beta"]);
+        let override_dir = game.output_dir().join("overrides");
+        files::write_file_with_string(override_dir.join("events/test.txt"), "by hand\r\n".to_owned(), false).unwrap();
+        let config = game.config(&["events"]).with_override_dir(Some(override_dir.clone()));
+        let args = game.args("Incremental", false).with_incremental(true);
+        let mod_pack = load_mod_pack(&config);
+
+        let report = generate_patch(&config, &args, &mod_pack, &ModReader::new(), &SilentProgress).unwrap();
+        assert_eq!(report.conflicts[0].outcome, MergeOutcome::Overridden);
+        assert_eq!(game.output("incremental/events/test.txt").unwrap(), "by hand\r\n");
+
+        // Nothing else changed, but the old override must not be reused once it is gone
+        fs::remove_file(override_dir.join("events/test.txt")).unwrap();
+        let report = generate_patch(&config, &args, &mod_pack, &ModReader::new(), &SilentProgress).unwrap();
+        assert_eq!((report.conflicts[0].strategy,report.conflicts[0].outcome), (MergeStrategy::AutoMerge,MergeOutcome::Merged));
+        let merged = game.output("incremental/events/test.txt").unwrap();
+        assert!(merged.contains("weight = 5") && merged.contains("weight = 9"));
+    }
+
+    #[test]
+    fn test_changed_diff_options_merge_again() {
+        let game = two_event_mods("diff-options-changed", false).enable(&["Alpha","Beta"]);
+        let config = game.config(&["events"]);
+        let args = game.args("Incremental", false).with_incremental(true);
+        let mod_pack = load_mod_pack(&config);
+
+        generate_patch(&config, &args, &mod_pack, &ModReader::new(), &SilentProgress).unwrap();
+        let report = generate_patch(&config, &args, &mod_pack, &ModReader::new(), &SilentProgress).unwrap();
+        assert_eq!(report.conflicts[0].outcome, MergeOutcome::Reused);
+
+        // Output merged with other settings for the folder is not reused
+        let tuned_options = DiffOptions {max_fuzz: 0, ..DiffOptions::default()};
+        let tuned = BTreeMap::from([(PathBuf::from("events"),tuned_options)]);
+        let config = config.with_diff_options(DiffOptions::default(), tuned);
+        let report = generate_patch(&config, &args, &mod_pack, &ModReader::new(), &SilentProgress).unwrap();
+        assert_eq!(report.conflicts[0].outcome, MergeOutcome::Merged);
+        let manifest = PatchManifest::from_json(&game.output(&format!("incremental/{}",MANIFEST_NAME)).unwrap()).unwrap();
+        assert_eq!(manifest.entries[0].diff_options, tuned_options);
+    }
+
+    #[test]
+    fn test_extract_all_files() {
+        let game = two_event_mods("extract", false)
+            .folder_mod("Gamma", &[("common/beta.txt", "gamma = yes\r\n")])
+            .enable(&["Alpha","Beta","Gamma"]);
+        let config = game.config(&["events","common"]);
+        let args = game.args("Everything", true);
+        let mod_pack = load_mod_pack(&config);
+        let reader = ModReader::new();
+
+        extract_all_files(&mod_pack, &args, &config, false, &game.output_dir(), &reader, &SilentProgress);
+        assert_eq!(game.output("everything/common/alpha.txt").unwrap(), "alpha = yes\r\n");
+        assert_eq!(game.output("everything/common/beta.txt").unwrap(), "gamma = yes\r\n");
+
+        // Mods later in load order win in the archive as well
+        extract_all_files(&mod_pack, &args, &config, true, &game.output_dir(), &reader, &SilentProgress);
+        let entries = zip_entries(&game.output_dir().join("everything.zip"));
+        assert_eq!(entries.len(), 3);
+        assert!(entries.contains(&("common/beta.txt".to_owned(),"gamma = yes\r\n".to_owned())));
+    }
+
+    #[test]
+    fn test_unreadable_file_leaves_zip_entry_to_earlier_mods() {
+        let game = two_event_mods("unreadable", false)
+            .folder_mod("Gamma", &[("common/beta.txt", "gamma = yes\r\n")])
+            .enable(&["Alpha","Beta","Gamma"]);
+        let config = game.config(&["events","common"]);
+        let args = game.args("Everything", true);
+        let mod_pack = load_mod_pack(&config);
+        let reader = ModReader::new();
+
+        // Gamma's copy goes missing after the mods were scanned, so Beta's copy must take its place
+        std::fs::remove_file(game.user_dir().join("mod").join("gamma").join("common").join("beta.txt")).unwrap();
+        let warnings = std::sync::Mutex::new(Vec::new());
+        let progress = |event: ProgressEvent| if let ProgressEvent::Warning { message, .. } = event {
+            warnings.lock().unwrap().push(message.to_owned());
+        };
+        extract_all_files(&mod_pack, &args, &config, true, &game.output_dir(), &reader, &progress);
+        let entries = zip_entries(&game.output_dir().join("everything.zip"));
+        assert_eq!(entries.len(), 3);
+        assert!(entries.contains(&("common/beta.txt".to_owned(),"beta = yes\r\n".to_owned())));
+
+        let warnings = warnings.into_inner().unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("Gamma"));
+    }
+}
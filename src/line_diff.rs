@@ -0,0 +1,217 @@
+// Line based diffs between two sequences, shared by the automatic merge and the unified diffs written for review
+use std::ops::Range;
+
+/// A single step in turning the old lines into the new lines
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum LineOp {
+    /// The old line at the first index is the same as the new line at the second
+    Equal(usize,usize),
+    /// The old line at this index was removed
+    Delete(usize),
+    /// The new line at this index was added
+    Insert(usize),
+}
+
+/// Finds the shortest edit script between two sequences of lines using Myers' algorithm
+/// The linear space variant is used, splitting at the middle of the edit script, so memory only grows with the length of the sequences
+/// Parts costing more than `MAX_COST` edits are split at the furthest point reached instead, so the script may then be a little longer than needed
+pub fn diff_lines<T: PartialEq>(old: &[T], new: &[T]) -> Vec<LineOp> {
+    let mut search = SnakeSearch::new(old.len() + new.len());
+    let mut ops = Vec::new();
+    conquer(old, new, 0..old.len(), 0..new.len(), &mut search, &mut ops);
+    ops
+}
+
+// Edits the search for a middle snake may spend on one part before settling for the furthest point reached
+// Bounds the time taken on large, heavily edited files, since each search takes time in the square of its edits
+const MAX_COST: usize = 256;
+
+// Furthest reaching x on every diagonal, searching forwards from the start and backwards from the end
+struct SnakeSearch {
+    forward: Vec<usize>,
+    backward: Vec<usize>,
+    offset: isize,
+}
+
+impl SnakeSearch {
+    fn new(total: usize) -> Self {
+        let size = total.min(2 * MAX_COST) + 3;
+        SnakeSearch {forward: vec![0; 2 * size + 1], backward: vec![0; 2 * size + 1], offset: size as isize}
+    }
+
+    fn at(&self, k: isize) -> usize {
+        (k + self.offset) as usize
+    }
+}
+
+// Matches the lines shared at the start, then splits what is left at a point on the edit script until one side runs out
+// Only the part before each split is recursed into, so the depth stays small even on long files
+fn conquer<T: PartialEq>(old: &[T], new: &[T], mut old_range: Range<usize>, mut new_range: Range<usize>, search: &mut SnakeSearch, ops: &mut Vec<LineOp>) {
+    loop {
+        while !old_range.is_empty() && !new_range.is_empty() && old[old_range.start] == new[new_range.start] {
+            ops.push(LineOp::Equal(old_range.start,new_range.start));
+            old_range.start += 1;
+            new_range.start += 1;
+        }
+        if old_range.is_empty() {
+            ops.extend(new_range.map(LineOp::Insert));
+            return;
+        }
+        if new_range.is_empty() {
+            ops.extend(old_range.map(LineOp::Delete));
+            return;
+        }
+        let (x,y) = middle_snake(old, new, old_range.clone(), new_range.clone(), search);
+        conquer(old, new, old_range.start..x, new_range.start..y, search, ops);
+        old_range.start = x;
+        new_range.start = y;
+    }
+}
+
+// Finds a point on a shortest edit script between two non-empty parts whose first lines differ, searching from both ends at once
+// Gives up after `MAX_COST` edits and returns the point the forward search got furthest to
+fn middle_snake<T: PartialEq>(old: &[T], new: &[T], old_range: Range<usize>, new_range: Range<usize>, search: &mut SnakeSearch) -> (usize,usize) {
+    let (old,new) = (&old[old_range.clone()],&new[new_range.clone()]);
+    let (n,m) = (old.len() as isize,new.len() as isize);
+    let delta = n - m;
+    let odd = delta % 2 != 0;
+    let split = |x: isize, y: isize| (old_range.start + x as usize, new_range.start + y as usize);
+    let start = search.at(1);
+    search.forward[start] = 0;
+    search.backward[start] = 0;
+
+    let max_d = ((n + m + 1) / 2).min(MAX_COST as isize);
+    for d in 0..=max_d {
+        for k in (-d..=d).step_by(2) {
+            let x = if k == -d || (k != d && search.forward[search.at(k-1)] < search.forward[search.at(k+1)]) {
+                search.forward[search.at(k+1)] as isize
+            } else {
+                search.forward[search.at(k-1)] as isize + 1
+            };
+            let (x0,y0) = (x,x-k);
+            let (mut x,mut y) = (x0,y0);
+            while x < n && y < m && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            let at_k = search.at(k);
+            search.forward[at_k] = x as usize;
+            if odd && (k - delta).abs() < d && x + search.backward[search.at(delta - k)] as isize >= n {
+                return split(x0,y0);
+            }
+        }
+        for k in (-d..=d).step_by(2) {
+            let x = if k == -d || (k != d && search.backward[search.at(k-1)] < search.backward[search.at(k+1)]) {
+                search.backward[search.at(k+1)] as isize
+            } else {
+                search.backward[search.at(k-1)] as isize + 1
+            };
+            let (mut x,mut y) = (x,x-k);
+            while x < n && y < m && old[(n-x-1) as usize] == new[(m-y-1) as usize] {
+                x += 1;
+                y += 1;
+            }
+            let at_k = search.at(k);
+            search.backward[at_k] = x as usize;
+            if !odd && (k - delta).abs() <= d && x + search.forward[search.at(delta - k)] as isize >= n {
+                return split(n-x,m-y);
+            }
+        }
+    }
+
+    // Too costly to finish, so take the furthest point inside both parts the forward search reached
+    let (x,y) = (-max_d..=max_d).step_by(2)
+        .map(|k| (search.forward[search.at(k)] as isize,k))
+        .map(|(x,k)| (x,x-k))
+        .filter(|(x,y)| *x <= n && *y >= 0 && *y <= m)
+        .max_by_key(|(x,y)| x + y)
+        .unwrap_or((n,0));
+    split(x,y)
+}
+
+/// Splits text into lines, accepting both windows and unix line endings
+pub fn split_lines(text: &str) -> Vec<&str> {
+    text.lines().collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_support::Lcg;
+
+    #[test]
+    fn test_diff_lines_rebuilds_new_text() {
+        let old = vec!["a","b","c","a","b","b","a"];
+        let new = vec!["c","b","a","b","a","c"];
+        let ops = diff_lines(&old, &new);
+        let rebuilt: Vec<&str> = ops.iter().filter_map(|op| match op {
+            LineOp::Equal(_,b) | LineOp::Insert(b) => Some(new[*b]),
+            LineOp::Delete(_) => None,
+        }).collect();
+        assert_eq!(rebuilt, new);
+        assert_eq!(ops.iter().filter(|op| !matches!(op, LineOp::Equal(..))).count(), 5);
+    }
+
+    // Checks the script walks both sequences in order and returns how many lines it changes
+    fn check_script(old: &[usize], new: &[usize], ops: &[LineOp]) -> usize {
+        let (mut x,mut y) = (0,0);
+        for op in ops {
+            match *op {
+                LineOp::Equal(a,b) => {
+                    assert_eq!((a,b,old[a]), (x,y,new[b]));
+                    x += 1;
+                    y += 1;
+                },
+                LineOp::Delete(a) => {
+                    assert_eq!(a, x);
+                    x += 1;
+                },
+                LineOp::Insert(b) => {
+                    assert_eq!(b, y);
+                    y += 1;
+                },
+            }
+        }
+        assert_eq!((x,y), (old.len(),new.len()));
+        ops.iter().filter(|op| !matches!(op, LineOp::Equal(..))).count()
+    }
+
+    // Fewest lines any script can change, from the longest common subsequence
+    fn fewest_changes(old: &[usize], new: &[usize]) -> usize {
+        let mut row = vec![0; new.len() + 1];
+        for a in old {
+            let mut diagonal = 0;
+            for (j,b) in new.iter().enumerate() {
+                let above = row[j + 1];
+                row[j + 1] = if a == b { diagonal + 1 } else { above.max(row[j]) };
+                diagonal = above;
+            }
+        }
+        old.len() + new.len() - 2 * row[new.len()]
+    }
+
+    #[test]
+    fn test_scripts_are_shortest() {
+        let mut rng = Lcg(7);
+        for _ in 0..500 {
+            let old: Vec<usize> = (0..rng.below(30)).map(|_| rng.below(4)).collect();
+            let new: Vec<usize> = (0..rng.below(30)).map(|_| rng.below(4)).collect();
+            assert_eq!(check_script(&old, &new, &diff_lines(&old, &new)), fewest_changes(&old, &new));
+        }
+    }
+
+    #[test]
+    fn test_large_heavily_edited_input() {
+        // A third of the lines differ, far more edits than one search may spend, as in a rewritten history/characters file
+        let mut rng = Lcg(11);
+        let old: Vec<usize> = (0..100_000).collect();
+        let new: Vec<usize> = old.iter().map(|line| match rng.below(6) {
+            0 => line + 1_000_000,
+            1 => line + 2_000_000,
+            _ => *line,
+        }).collect();
+        let changed = check_script(&old, &new, &diff_lines(&old, &new));
+        let replaced = old.iter().zip(&new).filter(|(a,b)| a != b).count();
+        assert!(changed >= 2 * replaced && changed < 2 * replaced + replaced / 10, "{} lines changed for {} replaced", changed, replaced);
+    }
+}
@@ -0,0 +1,163 @@
+use std::path::{Path,PathBuf};
+use std::sync::Mutex;
+use std::fs::File;
+use std::io::BufReader;
+
+use serde::{Deserialize,Serialize};
+use sha2::{Digest,Sha256};
+use zip::read::ZipArchive;
+
+use crate::io::{files,zips};
+use crate::io::encodings::{LineEnding,TextEncoding};
+use crate::merge_diff::DiffOptions;
+
+/// Name of the manifest file at the root of every generated patch
+pub const MANIFEST_NAME: &str = "merge_manifest.json";
+
+/// How the output for a conflicting file was produced
+#[derive(Serialize,Deserialize,Clone,Copy,Debug,PartialEq,Eq)]
+pub enum MergeStrategy {
+    /// Line based n-way diff of every mod against vanilla
+    AutoMerge,
+    /// The automatic merge failed, so every version was written out for manual merging
+    Manual,
+    /// The automatic merge failed, but the line based merge succeeded with saved resolutions for its clashing hunks
+    Resolved,
+    /// A hand-written file from the override folder was used instead of merging
+    Override,
+}
+
+/// A single mod's version of a conflicting file
+#[derive(Serialize,Deserialize,Clone,Debug,PartialEq,Eq)]
+pub struct ManifestInput {
+    pub mod_name: String,
+    pub mod_version: Option<String>,
+    pub hash: String,
+    /// Encoding the mod's version was read in
+    #[serde(default)]
+    pub encoding: TextEncoding,
+    /// Line ending the mod's version was read with, None if it has a single line
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub line_ending: Option<LineEnding>,
+}
+
+/// Record of how a single conflicting file was merged and from what
+#[derive(Serialize,Deserialize,Clone,Debug,PartialEq,Eq)]
+pub struct ManifestEntry {
+    pub path: PathBuf,
+    pub vanilla_hash: String,
+    /// Encoding the vanilla file was read in
+    #[serde(default)]
+    pub vanilla_encoding: TextEncoding,
+    /// Line ending the vanilla file was read with, None if it has a single line
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vanilla_line_ending: Option<LineEnding>,
+    pub inputs: Vec<ManifestInput>,
+    /// Encoding the merged file is written in
+    #[serde(default)]
+    pub output_encoding: TextEncoding,
+    /// Line ending the merged file is written with
+    #[serde(default)]
+    pub output_line_ending: LineEnding,
+    /// Settings the automatic merge used for the file
+    #[serde(default)]
+    pub diff_options: DiffOptions,
+    pub strategy: MergeStrategy,
+    pub merged: bool,
+}
+
+impl ManifestEntry {
+    /// Whether both entries were produced from identical inputs with the same merge settings, and are written in the same encoding and line ending
+    pub fn same_inputs(&self, other: &ManifestEntry) -> bool {
+        self.path == other.path && self.vanilla_hash == other.vanilla_hash && self.inputs == other.inputs && self.output_encoding == other.output_encoding && self.output_line_ending == other.output_line_ending
+            && self.diff_options == other.diff_options
+    }
+}
+
+/// Every conflicting file in a generated patch, in conflict order
+#[derive(Serialize,Deserialize,Clone,Debug,Default,PartialEq,Eq)]
+pub struct PatchManifest {
+    pub entries: Vec<ManifestEntry>,
+}
+
+impl PatchManifest {
+    pub fn new(entries: Vec<ManifestEntry>) -> Self {
+        PatchManifest {entries}
+    }
+
+    pub fn to_json(&self) -> Result<String,serde_json::Error> {
+        serde_json::to_string_pretty(self)
+    }
+
+    pub fn from_json(contents: &str) -> Result<Self,serde_json::Error> {
+        serde_json::from_str(contents)
+    }
+
+    pub fn find(&self, path: &Path) -> Option<&ManifestEntry> {
+        self.entries.iter().find(|entry| entry.path == path)
+    }
+}
+
+/// SHA-256 digest of a file's contents in hex, as stored in the manifest
+/// `--incremental` reuses an output whenever these match, so the digest has to be collision resistant rather than just fast
+pub fn content_hash(contents: &str) -> String {
+    Sha256::digest(contents.as_bytes()).iter().map(|byte| format!("{:02x}",byte)).collect()
+}
+
+/// Where the files of a previously generated patch can be read from
+enum PatchSource {
+    Folder(PathBuf),
+    Zip(Mutex<ZipArchive<BufReader<File>>>),
+}
+
+/// A patch from an earlier run, whose merged files can be reused when their inputs have not changed
+pub struct PreviousPatch {
+    manifest: PatchManifest,
+    source: PatchSource,
+}
+
+impl PreviousPatch {
+    /// Opens the patch folder or zip file previously written to the output directory
+    /// Returns None if there is no previous patch, or it has no readable manifest
+    /// # Arguments
+    ///
+    /// * `output_root` - directory the patch was written into
+    ///
+    /// * `folder_name` - name of the patch folder, without any extension
+    pub fn open(output_root: &Path, folder_name: &str) -> Option<Self> {
+        let folder = output_root.join(folder_name);
+        let zip_path = output_root.join(format!("{}.zip",folder_name));
+        if folder.is_dir() {
+            let manifest_text = files::fetch_file_in_path(&folder.join(MANIFEST_NAME), false, false)?;
+            let manifest = PatchManifest::from_json(&manifest_text).ok()?;
+            Some(PreviousPatch {manifest, source: PatchSource::Folder(folder)})
+        } else if zip_path.is_file() {
+            let mut archive = zips::zip_open(&zip_path).ok()?;
+            let manifest_bytes = zips::zip_read_entry(&mut archive, MANIFEST_NAME).ok()?;
+            let manifest = PatchManifest::from_json(&String::from_utf8(manifest_bytes).ok()?).ok()?;
+            Some(PreviousPatch {manifest, source: PatchSource::Zip(Mutex::new(archive))})
+        } else {
+            None
+        }
+    }
+
+    /// Fetches the previously merged output for an entry, if it was merged from exactly the same inputs
+    /// # Arguments
+    ///
+    /// * `entry` - manifest entry describing the current inputs of the conflict
+    pub fn reusable_output(&self, entry: &ManifestEntry) -> Option<(MergeStrategy,Vec<u8>)> {
+        let previous = self.manifest.find(&entry.path)?;
+        if !previous.merged || !previous.same_inputs(entry) {
+            return None;
+        }
+        let contents = match &self.source {
+            PatchSource::Folder(folder) => files::fetch_bytes_in_path(&folder.join(&entry.path)).ok()?,
+            PatchSource::Zip(archive) => {
+                let mut archive = archive.lock().ok()?;
+                let entry_name = entry.path.to_str()?.replace('\\', "/");
+                zips::zip_read_entry(&mut archive, &entry_name).ok()?
+            },
+        };
+        Some((previous.strategy,contents))
+    }
+}
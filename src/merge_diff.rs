@@ -1,133 +1,515 @@
-use diff_match_patch::{Dmp,Diff};
+use serde::{Deserialize,Serialize};
 use std::collections::HashMap;
-// Use these to configure the diff match patch engine
-// I am having to just do trial and error until they maximize test passes
+use std::ops::Range;
 
-// Use this for maximum allowed change on finding a patch
-static MATCH_THRESHOLD: f32 = 0.22;//0.22;
-// Use this for maximum allowed change on either side of a deleted set of chars
-//static DELETE_THRESHOLD: f32 = 0.0;
-// Maximum patch length allowed
-//static MATCH_DIST: i32 = 500;
-// Maximum size of a single patch
-static MATCH_BITS: i32 = 16;
+use crate::line_diff::{diff_lines,split_lines,LineOp};
 
-// Generate a diff match patch engine with our custom settings
-fn create_dmp_preconfig() -> Dmp {
-    let mut dmp = Dmp::new();
-    //dmp.match_distance = MATCH_DIST;
-    dmp.match_threshold = MATCH_THRESHOLD;
-    //dmp.patch_delete_threshold = DELETE_THRESHOLD;
-    dmp.match_maxbits = MATCH_BITS;
+/// Settings of the automatic merge
+/// Each can be set per game and per folder, and `benchmark` scores them against a corpus of known merges
+#[derive(Serialize,Deserialize,Clone,Copy,Debug,PartialEq,Eq)]
+#[serde(from = "DiffSettings")]
+pub struct DiffOptions {
+    /// Context lines on either side of a hunk that other mods may change before the hunk is refused
+    pub max_fuzz: usize,
+    /// Base lines on either side of a hunk that are checked for changes by other mods
+    pub context_lines: usize,
+    /// Whether comments are left out when lines are compared, so a mod that only changes comments changes nothing
+    pub ignore_comments: bool,
+    /// Whether indentation is left out when lines are compared, so a mod that only reindents changes nothing
+    pub ignore_indentation: bool,
+}
+
+impl Default for DiffOptions {
+    fn default() -> Self {
+        DiffOptions {
+            max_fuzz: 2,
+            context_lines: 4,
+            ignore_comments: true,
+            ignore_indentation: false,
+        }
+    }
+}
+
+/// Settings of the automatic merge as written in `merger.toml`, including those of the diff match patch merge it replaced
+/// `match_bits` and `match_threshold` are read as `context_lines` and a share of them for `max_fuzz`, unless those are set as well
+/// `match_distance` and `delete_threshold` have no counterpart, since hunks are placed by the vanilla lines they change and deletions must match exactly
+#[derive(Serialize,Deserialize,Clone,Debug,Default)]
+#[serde(default)]
+pub struct DiffSettings {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_fuzz: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub context_lines: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ignore_comments: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ignore_indentation: Option<bool>,
+    /// Deprecated, share of the context lines other mods may change
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub match_threshold: Option<f64>,
+    /// Deprecated, lines of context around each hunk
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub match_bits: Option<usize>,
+    /// Deprecated and ignored
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub match_distance: Option<f64>,
+    /// Deprecated and ignored
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub delete_threshold: Option<f64>,
+}
+
+impl DiffSettings {
+    /// Names of the settings that are set but no longer change the merge
+    pub fn ignored(&self) -> Vec<&'static str> {
+        let mut ignored = Vec::new();
+        if self.match_distance.is_some() {
+            ignored.push("match_distance");
+        }
+        if self.delete_threshold.is_some() {
+            ignored.push("delete_threshold");
+        }
+        ignored
+    }
+}
+
+impl From<DiffSettings> for DiffOptions {
+    fn from(settings: DiffSettings) -> Self {
+        let default = DiffOptions::default();
+        let context_lines = settings.context_lines.or(settings.match_bits).unwrap_or(default.context_lines);
+        let max_fuzz = settings.max_fuzz
+            .or_else(|| settings.match_threshold.map(|threshold| (threshold.clamp(0.0,1.0) * context_lines as f64).round() as usize))
+            .unwrap_or(default.max_fuzz);
+        DiffOptions {
+            max_fuzz,
+            context_lines,
+            ignore_comments: settings.ignore_comments.unwrap_or(default.ignore_comments),
+            ignore_indentation: settings.ignore_indentation.unwrap_or(default.ignore_indentation),
+        }
+    }
+}
 
-    dmp
+impl From<DiffOptions> for DiffSettings {
+    fn from(options: DiffOptions) -> Self {
+        DiffSettings {
+            max_fuzz: Some(options.max_fuzz),
+            context_lines: Some(options.context_lines),
+            ignore_comments: Some(options.ignore_comments),
+            ignore_indentation: Some(options.ignore_indentation),
+            ..DiffSettings::default()
+        }
+    }
 }
 
 // True means keep, false means don't
-fn preprocess_text(input_text: &str, comments: bool, empty_lines: bool, trailing: bool, leading: bool) -> String {
-    let mut output = String::new();
-    for line in input_text.split("\r\n") {
-        let mut new_line = line.to_owned();
+// Lines may end in either \n or \r\n, each kept line is returned with its line number counting from one
+fn preprocess_lines(input_text: &str, comments: bool, empty_lines: bool, trailing: bool, leading: bool) -> Vec<(usize,String)> {
+    let mut output = Vec::new();
+    for (index,line) in input_text.split('\n').map(|line| line.strip_suffix('\r').unwrap_or(line)).enumerate() {
+        let mut new_line = line;
         if !comments {
-            if let Some(s) = line.splitn(2,'#').next() {
-                new_line = s.to_owned();
+            if let Some(s) = line.split('#').next() {
+                new_line = s;
             }
         }
 
         if !trailing {
-            new_line = new_line.trim_end().to_owned();
+            new_line = new_line.trim_end();
         }
 
         if !leading {
-            new_line = new_line.trim_start().to_owned();
+            new_line = new_line.trim_start();
         }
 
         if !new_line.is_empty() || empty_lines {
-            output.push_str("\r\n");
-            output.push_str(&new_line);
+            output.push((index + 1,new_line.to_owned()));
         }
     }
 
     output
 }
 
-fn diff_linemode_nway(base_text: &str, modified_text: &[String]) -> (HashMap<char,String>,Vec<Vec<Diff>>,String) {
-    let mut all_strings = modified_text.to_vec();
-    let mut encoded_strings = Vec::new();
-    all_strings.insert(0,base_text.to_owned());
-    
-    let mut char_to_line = HashMap::new();
-    let mut line_to_char: HashMap<String,char> = HashMap::new();
-    let mut current_idx: u32 = 128;
-    let mut diffs = Vec::new();
+// The lines the automatic merge compares, without blank lines, trailing whitespace or whatever else the settings leave out
+fn merged_lines(input_text: &str, options: &DiffOptions) -> Vec<(usize,String)> {
+    preprocess_lines(input_text, !options.ignore_comments, false, false, !options.ignore_indentation)
+}
 
-    for text in all_strings {
-        let real_text = preprocess_text(&text,false,false,false,true);
-        
-        let mut encoded_text = String::new();
-        for line in real_text.split("\r\n") {
-        if !line_to_char.contains_key(line) {
-            current_idx+=1;
-            while std::char::from_u32(current_idx).is_none() {
-                current_idx+=1;
+// Every distinct line is given a number, so texts of any length are diffed as sequences of numbers
+#[derive(Default)]
+struct LineTokens {
+    ids: HashMap<String,usize>,
+}
+
+// A text as the merge compares it, one token for every line that is not blank or a comment
+// Each token keeps the lines it was read from, so merged files are written as the mods wrote them
+struct TokenText<'a> {
+    tokens: Vec<usize>,
+    // Every line of the text, without its line ending
+    lines: Vec<&'a str>,
+    // The lines each token stands for, the blank and comment lines before a line going with it
+    spans: Vec<Range<usize>>,
+}
+
+impl<'a> TokenText<'a> {
+    // Blank and comment lines after the last compared line, along with an empty line for a final line ending
+    fn tail(&self) -> &[&'a str] {
+        &self.lines[self.spans.last().map(|span| span.end).unwrap_or(0)..]
+    }
+}
+
+impl LineTokens {
+    // Every text starts with an empty line, so that changes to the first line have context
+    fn tokenize<'a>(&mut self, text: &'a str, options: &DiffOptions) -> TokenText<'a> {
+        let next_id = self.ids.len();
+        let mut tokens = vec![*self.ids.entry(String::new()).or_insert(next_id)];
+        let mut spans = Vec::new();
+        spans.push(0..0);
+        for (line,compared) in merged_lines(text, options) {
+            let next_id = self.ids.len();
+            tokens.push(*self.ids.entry(compared).or_insert(next_id));
+            spans.push(spans[spans.len() - 1].end..line);
+        }
+        let lines = text.split('\n').map(|line| line.strip_suffix('\r').unwrap_or(line)).collect();
+        TokenText {tokens, lines, spans}
+    }
+}
+
+// A merged line as the text it comes from, zero for the base and one more than the version for a mod, and its token position there
+type LineSource = (usize,usize);
+
+// One mod's change to a run of base lines
+struct TokenHunk {
+    // The base lines the hunk replaces, empty for a hunk that only adds lines
+    removed: Range<usize>,
+    added: Vec<usize>,
+    // Index of the first added line in the mod's version
+    mod_start: usize,
+}
+
+// Splits the line diff between the base and one mod's version into hunks, one for each run of changed lines
+fn make_hunks(base: &[usize], modded: &[usize]) -> Vec<TokenHunk> {
+    let mut hunks = Vec::new();
+    let mut current: Option<TokenHunk> = None;
+    let (mut base_line,mut mod_line) = (0,0);
+    for op in diff_lines(base, modded) {
+        if let LineOp::Equal(..) = op {
+            hunks.extend(current.take());
+            base_line += 1;
+            mod_line += 1;
+            continue;
+        }
+        let hunk = current.get_or_insert_with(|| TokenHunk {removed: base_line..base_line, added: Vec::new(), mod_start: mod_line});
+        match op {
+            LineOp::Delete(_) => {
+                hunk.removed.end += 1;
+                base_line += 1;
+            },
+            LineOp::Insert(b) => {
+                hunk.added.push(modded[b]);
+                mod_line += 1;
+            },
+            LineOp::Equal(..) => (),
+        }
+    }
+    hunks.extend(current);
+    hunks
+}
+
+// Whether two hunks of different mods change the same base lines in different ways
+// Hunks that only add lines clash when they add them at the same place, since neither order would be right
+fn clashes(a: &TokenHunk, b: &TokenHunk) -> bool {
+    let (a_removed,b_removed) = (&a.removed,&b.removed);
+    if a_removed == b_removed && a.added == b.added {
+        return false;
+    }
+    if a_removed.is_empty() && b_removed.is_empty() {
+        return a_removed.start == b_removed.start;
+    }
+    a_removed.start < b_removed.end && b_removed.start < a_removed.end
+}
+
+// Merges every mod's hunks into the base, keeping any hunk that neither clashes with an earlier mod's nor has too much of its context changed
+// Every hunk is placed and checked by the base lines it changes, never by what earlier mods made of them,
+// so a merge that succeeds gives the same text whatever order the mods come in
+// Returns where each merged line comes from, along with where each hunk went and the base lines that could not be merged, in token positions
+fn merge_hunks(base: &[usize], mod_hunks: &[Vec<TokenHunk>], options: &DiffOptions) -> (Vec<LineSource>,Vec<HunkPlacement>,Vec<Range<usize>>) {
+    let hunks: Vec<(usize,&TokenHunk)> = mod_hunks.iter().enumerate().flat_map(|(version,hunks)| hunks.iter().map(move |hunk| (version,hunk))).collect();
+
+    // Every mod changing each base line, to count the context lines of a hunk that other mods change
+    let mut changed_by: Vec<Vec<usize>> = vec![Vec::new(); base.len()];
+    for (version,hunk) in &hunks {
+        for line in hunk.removed.clone() {
+            changed_by[line].push(*version);
+        }
+    }
+    let fuzz: Vec<usize> = hunks.iter().map(|(version,hunk)| {
+        let removed = &hunk.removed;
+        let changed = |lines: Range<usize>| lines.filter(|line| changed_by[*line].iter().any(|other| other != version)).count();
+        changed(removed.start.saturating_sub(options.context_lines)..removed.start).max(changed(removed.end..(removed.end + options.context_lines).min(base.len())))
+    }).collect();
+
+    // Hunks sorted by where they start only need comparing with those starting before they end
+    let mut by_start: Vec<usize> = (0..hunks.len()).collect();
+    by_start.sort_by_key(|index| hunks[*index].1.removed.start);
+    let mut clash_pairs: Vec<(usize,usize)> = Vec::new();
+    for (position,first) in by_start.iter().enumerate() {
+        let first_end = hunks[*first].1.removed.end;
+        for second in by_start[position + 1..].iter().take_while(|second| hunks[**second].1.removed.start <= first_end) {
+            if hunks[*first].0 != hunks[*second].0 && clashes(hunks[*first].1, hunks[*second].1) {
+                clash_pairs.push((*first.min(second),*first.max(second)));
             }
-            line_to_char.insert(line.to_owned(), std::char::from_u32(current_idx).unwrap());
         }
-        if let Some(c) = line_to_char.get(line) {
-            encoded_text.push(*c);
-        } else {
-            eprintln!{"Something has gone horribly wrong in the n-way diff."};
+    }
+
+    // Hunks are in load order, so of two clashing hunks the one from the earlier mod is kept
+    let mut applied = vec![false; hunks.len()];
+    for index in 0..hunks.len() {
+        let beaten = clash_pairs.iter().any(|(earlier,later)| *later == index && applied[*earlier]);
+        applied[index] = fuzz[index] <= options.max_fuzz && !beaten;
+    }
+
+    // The lines that cannot be merged only depend on which hunks clash or are crowded, not on which of them was kept
+    let mut unmerged: Vec<Range<usize>> = clash_pairs.iter().map(|(a,b)| {
+        let (a,b) = (&hunks[*a].1.removed,&hunks[*b].1.removed);
+        a.start.min(b.start)..a.end.max(b.end)
+    }).collect();
+    unmerged.extend(hunks.iter().zip(&fuzz).filter(|(_,fuzz)| **fuzz > options.max_fuzz).map(|((_,hunk),_)| hunk.removed.clone()));
+    unmerged.sort_by_key(|range| (range.start,range.end));
+    let mut conflicts: Vec<Range<usize>> = Vec::new();
+    for range in unmerged {
+        match conflicts.last_mut() {
+            Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+            _ => conflicts.push(range),
         }
     }
-        encoded_strings.push(encoded_text);
+
+    // Kept hunks go in by where they start in the base, lines added in front of a replacement going first
+    let mut kept: Vec<usize> = (0..hunks.len()).filter(|index| applied[*index]).collect();
+    kept.sort_by_key(|index| (hunks[*index].1.removed.start,hunks[*index].1.removed.end,*index));
+    let mut merged = Vec::with_capacity(base.len());
+    let mut offsets = vec![0; hunks.len()];
+    let mut position = 0;
+    let mut last: Option<&TokenHunk> = None;
+    for index in kept {
+        let hunk = hunks[index].1;
+        let removed = &hunk.removed;
+        // Several mods making the same change only add it once
+        let repeated = last.map(|last| last.removed == *removed && last.added == hunk.added).unwrap_or(false);
+        if !repeated {
+            merged.extend((position..removed.start).map(|line| (0,line)));
+        }
+        offsets[index] = (merged.len() - if repeated { hunk.added.len() } else { 0 }) as i64 - hunk.mod_start as i64;
+        if !repeated {
+            merged.extend((hunk.mod_start..hunk.mod_start + hunk.added.len()).map(|line| (hunks[index].0 + 1,line)));
+            position = removed.end;
+        }
+        last = Some(hunk);
     }
+    merged.extend((position..base.len()).map(|line| (0,line)));
+
+    let placements = hunks.iter().enumerate().map(|(index,(version,hunk))| HunkPlacement {
+        version: *version,
+        line: hunk.removed.start,
+        applied: applied[index],
+        offset: offsets[index],
+        fuzz: fuzz[index],
+    }).collect();
+    (merged,placements,conflicts)
+}
+
+/// Where a single hunk of one mod's changes went
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub struct HunkPlacement {
+    /// Index of the modded text the hunk comes from
+    pub version: usize,
+    /// Line of the base text the hunk changes, counting from one
+    pub line: usize,
+    /// False if the hunk clashed with an earlier mod's or too much of its context was changed, so it was left out
+    pub applied: bool,
+    /// Lines other mods added or removed before the hunk, moving it from where it is in its mod's version
+    pub offset: i64,
+    /// Most context lines on either side of the hunk that other mods change
+    pub fuzz: usize,
+}
 
-    let mut dmp = create_dmp_preconfig();
-    for text_2 in encoded_strings.iter().skip(1) {
-        let mut diff = dmp.diff_main(&encoded_strings[0], text_2, false);
-        dmp.diff_cleanup_efficiency(&mut diff);
-        diffs.push(diff);
+/// Result of merging every mod's version of a file against vanilla
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub struct DiffOutcome {
+    /// Merged file contents, None if any hunk could not be applied
+    pub text: Option<String>,
+    /// Every hunk that could be applied, kept when others could not so they need not be merged again by hand
+    pub partial: Option<String>,
+    /// Number of hunks from all mods that applied cleanly
+    pub hunks_merged: usize,
+    /// Number of hunks from all mods that clashed with an earlier change
+    pub hunks_conflicted: usize,
+    /// Every hunk of every mod, in load order
+    pub hunks: Vec<HunkPlacement>,
+    /// Base lines that could not be merged, counting from one and not including the end, empty ranges being where lines were added
+    /// Unlike which hunks were left out, these do not depend on the order of the mods
+    pub conflicts: Vec<Range<usize>>,
+}
+
+/// Merges every modded text against the base text with the given settings, keeping track of how many hunks applied
+pub fn diff_single_conflict_with(base_text: &str, modded_texts: &[String], options: &DiffOptions) -> DiffOutcome {
+    let mut tokens = LineTokens::default();
+    let texts: Vec<TokenText> = std::iter::once(base_text).chain(modded_texts.iter().map(String::as_str)).map(|text| tokens.tokenize(text, options)).collect();
+    let base = &texts[0];
+    let mod_hunks: Vec<Vec<TokenHunk>> = texts[1..].iter().map(|text| make_hunks(&base.tokens, &text.tokens)).collect();
+    let (patched,mut hunks,token_conflicts) = merge_hunks(&base.tokens, &mod_hunks, options);
+    let mut merged_lines: Vec<&str> = Vec::with_capacity(base.lines.len());
+    for (text,position) in patched {
+        merged_lines.extend_from_slice(&texts[text].lines[texts[text].spans[position].clone()]);
     }
+    merged_lines.extend_from_slice(base.tail());
+    let merged = merged_lines.join("\r\n");
 
-    for (key,val) in line_to_char {
-        char_to_line.insert(val, key);
+    // Positions count the empty line every tokenized text starts with, so map them back to lines of the base text
+    let line_of = |token: usize| match token {
+        0 => 1,
+        _ => base.spans.get(token).map(|span| span.end).unwrap_or_else(|| base_text.lines().count() + 1),
+    };
+    for hunk in &mut hunks {
+        hunk.line = line_of(hunk.line);
     }
+    let conflicts = token_conflicts.into_iter().map(|range| match range.is_empty() {
+        true => line_of(range.start)..line_of(range.start),
+        false => line_of(range.start)..line_of(range.end - 1) + 1,
+    }).collect();
+    let hunks_merged = hunks.iter().filter(|hunk| hunk.applied).count();
+    let hunks_conflicted = hunks.len() - hunks_merged;
+    let (text,partial) = if hunks_conflicted > 0 { (None,Some(merged)) } else { (Some(merged),None) };
+    DiffOutcome {text, partial, hunks_merged, hunks_conflicted, hunks, conflicts}
+}
+
+/// A run of lines in the result of a line based merge
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub enum MergeChunk {
+    /// Lines that merged cleanly, either untouched or changed the same way by every mod that changed them
+    Clean(Vec<String>),
+    /// Vanilla lines that several mods changed in different ways
+    Conflict(ConflictHunk),
+}
 
-    (char_to_line,diffs,encoded_strings[0].clone())
+/// Vanilla lines along with every mod's replacement for them
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub struct ConflictHunk {
+    /// Index of the first vanilla line in the hunk
+    pub base_start: usize,
+    pub base: Vec<String>,
+    /// Each mod's replacement for the vanilla lines in load order, None if the mod left them alone
+    pub versions: Vec<Option<Vec<String>>>,
 }
 
-fn patch_nway(source_text: &str, diffs: &mut Vec<Vec<Diff>>) -> Option<String> {
-    let mut dmp = create_dmp_preconfig();
-    let mut result_text = source_text.to_owned();
-    for diff in diffs {
-        let mut patch = dmp.patch_make4(source_text, diff);
-        let (changed_text,applied_patches) = dmp.patch_apply(&mut patch, &result_text);
-        if !applied_patches.iter().fold(true, |a,b| a & b) {
-            return None;
+impl ConflictHunk {
+    /// Every mod's replacement in load order, leaving out identical ones, so taking both sides keeps a change only once
+    pub fn all_in_order(&self) -> Vec<String> {
+        let mut seen: Vec<&Vec<String>> = Vec::new();
+        for version in self.versions.iter().flatten() {
+            if !seen.contains(&version) {
+                seen.push(version);
+            }
         }
-        result_text = changed_text.iter().collect();
+        seen.into_iter().flatten().cloned().collect()
     }
-    Some(result_text)
 }
 
-pub fn diff_single_conflict(base_text: &str, modded_texts: &[String], _verbose: bool) -> Option<String> {
-    let (character_map,mut diffs,encoded_base) = diff_linemode_nway(base_text, modded_texts);
-    let encoded_patched = patch_nway(&encoded_base, &mut diffs)?;
-    // Now decode
-    let mut result_text = String::new();
-    for character in encoded_patched.chars() {
-        let line = character_map.get(&character)?;
-        result_text.push_str(line);
-        result_text.push_str("\r\n");
+// Lines one mod replaced, from `start` up to but not including `end`
+struct LineEdit<'a> {
+    start: usize,
+    end: usize,
+    mod_index: usize,
+    lines: Vec<&'a str>,
+}
+
+/// Merges every modded text against the base text line by line, keeping clashing changes apart instead of failing
+/// Changes touching separate vanilla lines merge cleanly, as do changes that several mods made identically
+pub fn merge_lines(base_text: &str, modded_texts: &[String]) -> Vec<MergeChunk> {
+    let base = split_lines(base_text);
+    let mut edits: Vec<LineEdit> = Vec::new();
+    for (mod_index,text) in modded_texts.iter().enumerate() {
+        let lines = split_lines(text);
+        let mut position = 0;
+        let mut current: Option<LineEdit> = None;
+        for op in diff_lines(&base, &lines) {
+            match op {
+                LineOp::Equal(a,_) => {
+                    edits.extend(current.take());
+                    position = a + 1;
+                },
+                LineOp::Delete(a) => {
+                    current.get_or_insert(LineEdit {start: position, end: position, mod_index, lines: Vec::new()}).end = a + 1;
+                    position = a + 1;
+                },
+                LineOp::Insert(b) => current.get_or_insert(LineEdit {start: position, end: position, mod_index, lines: Vec::new()}).lines.push(lines[b]),
+            }
+        }
+        edits.extend(current);
+    }
+    edits.sort_by_key(|edit| (edit.start, edit.mod_index));
+
+    let mut chunks = Vec::new();
+    let mut clean: Vec<String> = Vec::new();
+    let mut position = 0;
+    let mut edits = edits.into_iter().peekable();
+    while let Some(first) = edits.next() {
+        // Overlapping edits, and insertions at the same place, have to be settled together
+        let start = first.start;
+        let mut end = first.end;
+        let mut group = vec![first];
+        while let Some(next) = edits.next_if(|next| next.start < end || next.start == start) {
+            end = end.max(next.end);
+            group.push(next);
+        }
+
+        clean.extend(base[position..start].iter().map(|line| line.to_string()));
+        position = end;
+        let versions: Vec<Option<Vec<String>>> = (0..modded_texts.len()).map(|mod_index| {
+            let mut cursor = start;
+            let mut version: Option<Vec<String>> = None;
+            for edit in group.iter().filter(|edit| edit.mod_index == mod_index) {
+                let lines = version.get_or_insert_with(Vec::new);
+                lines.extend(base[cursor..edit.start].iter().map(|line| line.to_string()));
+                lines.extend(edit.lines.iter().map(|line| line.to_string()));
+                cursor = edit.end;
+            }
+            version.map(|mut lines| {
+                lines.extend(base[cursor..end].iter().map(|line| line.to_string()));
+                lines
+            })
+        }).collect();
+
+        let mut changed = versions.iter().flatten();
+        let first_version = changed.next().cloned().unwrap_or_default();
+        if changed.all(|version| *version == first_version) {
+            clean.extend(first_version);
+        } else {
+            if !clean.is_empty() {
+                chunks.push(MergeChunk::Clean(std::mem::take(&mut clean)));
+            }
+            let base = base[start..end].iter().map(|line| line.to_string()).collect();
+            chunks.push(MergeChunk::Conflict(ConflictHunk {base_start: start, base, versions}));
+        }
     }
-    let _a = result_text.pop();
-    let _a = result_text.pop();
-    Some(result_text)
+    clean.extend(base[position..].iter().map(|line| line.to_string()));
+    if !clean.is_empty() {
+        chunks.push(MergeChunk::Clean(clean));
+    }
+    chunks
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::test_support::Lcg;
+
+    fn diff_single_conflict_detailed(base_text: &str, modded_texts: &[String]) -> DiffOutcome {
+        diff_single_conflict_with(base_text, modded_texts, &DiffOptions::default())
+    }
+
+    fn diff_single_conflict(base_text: &str, modded_texts: &[String]) -> Option<String> {
+        diff_single_conflict_detailed(base_text, modded_texts).text
+    }
+
     #[test]
     fn test_diff_two_line_changes() {
         let source = "OR = \r\n{\r\n\ttier = KING\r\n\tcash = 240\r\n\treligion = rustacean\r\n}\r\n".to_owned();
@@ -136,7 +518,7 @@ mod test {
         
         let both_changes = "OR = \r\n{\r\n\ttier = COUNT\r\n\teggs = 89\r\n\treligion = rustacean\r\n}\r\n".to_owned();
         
-        assert_eq!(diff_single_conflict(&source, &[change_tier,change_cash], true),Some(both_changes));
+        assert_eq!(diff_single_conflict(&source, &[change_tier,change_cash]),Some(both_changes));
     }
     
     #[test]
@@ -147,7 +529,7 @@ mod test {
         
         let both_changes = "OR = \r\n{\r\n\treligion = rustacean\r\n}\r\n".to_owned();
         
-        assert_eq!(diff_single_conflict(&source, &[no_tier,no_cash], true),Some(both_changes));
+        assert_eq!(diff_single_conflict(&source, &[no_tier,no_cash]),Some(both_changes));
     }
     
     #[test]
@@ -158,7 +540,7 @@ mod test {
         
         let both_changes = "OR = \r\n{\r\n\t AND = {\r\n\tbob = jim\r\n\t zoop = zorp}\r\n\treligion = rustacean\r\n}\r\n".to_owned();
         
-        assert_eq!(diff_single_conflict(&source, &[no_tier,change_cash], true),Some(both_changes));
+        assert_eq!(diff_single_conflict(&source, &[no_tier,change_cash]),Some(both_changes));
     }
     
     #[test]
@@ -167,7 +549,7 @@ mod test {
         let change_tier_1 = "OR = \r\n{\r\n\ttier = DUKE\r\n\tcash = 240\r\n\treligion = rustacean\r\n}\r\n".to_owned();
         let change_tier_2 = "OR = \r\n{\r\n\ttier = COUNT\r\n\tcash = 240\r\n\treligion = rustacean\r\n}\r\n".to_owned();
         
-        assert_eq!(diff_single_conflict(&source, &[change_tier_1,change_tier_2], false),None);
+        assert_eq!(diff_single_conflict(&source, &[change_tier_1,change_tier_2]),None);
     }
     
     
@@ -178,7 +560,7 @@ mod test {
         let change_tier_2 = "OR = \r\n{\r\n\ttier = COUNT\r\n\tcash = 240\r\n\treligion = rustacean\r\n}\r\n".to_owned();
         let change_tier_3 = "OR = \r\n{\r\n\ttier = EMPEROR\r\n\tcash = 240\r\n\treligion = rustacean\r\n}\r\n".to_owned();
         
-        assert_eq!(diff_single_conflict(&source, &[change_tier_1,change_tier_2,change_tier_3], false),None);
+        assert_eq!(diff_single_conflict(&source, &[change_tier_1,change_tier_2,change_tier_3]),None);
     }
     
     #[test]
@@ -188,9 +570,9 @@ mod test {
         let source_o = "OR = \r\n{\r\n\ttier = KONG\r\n\tcash = 240\r\n\treligion = rustacean\r\n}\r\n".to_owned();
         
         // Result should be a failure
-        assert_eq!(diff_single_conflict(&source,&[source_a.clone(),source_o.clone()],false),None, "Conflicting entries on a line, even by a single character, should not produce output text.");
+        assert_eq!(diff_single_conflict(&source,&[source_a.clone(),source_o.clone()]),None, "Conflicting entries on a line, even by a single character, should not produce output text.");
         // Result should not depend on patch order if it will fail
-        assert_eq!(diff_single_conflict(&source,&[source_a.clone(),source_o.clone()],false),diff_single_conflict(&source,&[source_o,source_a],false),"Result changes based off of the order of the changed files.");
+        assert_eq!(diff_single_conflict(&source,&[source_a.clone(),source_o.clone()]),diff_single_conflict(&source,&[source_o,source_a]),"Result changes based off of the order of the changed files.");
     }
 
     #[test]
@@ -201,8 +583,8 @@ mod test {
 
         let result = "this is some\r\nAAAAAAAAAAAAAAAAASJHljshdflshjdfhsdjfhjsdhfljshflsdfhsd\r\na lot of\r\nsdkjfhskjdhfkjshdfkjnsdjfhkjsdhfuhsdfhljsdfljshdfjsdfhljsdhfljsdhfysdofh      \t   \r\ntext that will have many lines added in the middle with\r\na lot of changes in the middle that makes a lot of gibberish the content".to_owned();
 
-        assert_eq!(diff_single_conflict(source, &[big_change.clone(),small_change_later.clone()], true),Some(result.clone()));
-        assert_eq!(diff_single_conflict(source, &[small_change_later,big_change], true),Some(result));
+        assert_eq!(diff_single_conflict(source, &[big_change.clone(),small_change_later.clone()]),Some(result.clone()));
+        assert_eq!(diff_single_conflict(source, &[small_change_later,big_change]),Some(result));
     }
 
     #[test]
@@ -213,8 +595,8 @@ mod test {
 
         let result = "this is some\r\nAAAAAAAAAAAAAAAAASJHljshdflshjdfhsdjfhjsdhfljshflsdfhsd\r\na lot of\r\nsdkjfhskjdhfkjshdfkjnsdjfhkjsdhfuhsdfhljsdfljshdfjsdfhljsdhfljsdhfysdofh      \t   \r\ntext that will have many lines added in the middle with\r\na lot of changes in the middle that makes a lot of gibberish the content\r\nthis doesn't go away".to_owned();
 
-        assert_eq!(diff_single_conflict(source, &[big_change.clone(),small_change_later.clone()], true),Some(result.clone()));
-        assert_eq!(diff_single_conflict(source, &[small_change_later,big_change], true),Some(result));
+        assert_eq!(diff_single_conflict(source, &[big_change.clone(),small_change_later.clone()]),Some(result.clone()));
+        assert_eq!(diff_single_conflict(source, &[small_change_later,big_change]),Some(result));
     }
 
     #[test]
@@ -223,6 +605,291 @@ mod test {
         let change_line = "This is my original text\r\n\r\nthis is a line is super duper balanced\r\nluckily the changed text is better.".to_owned();
         let remove_line = "This is my original text\r\n\r\nluckily the changed text is better.".to_owned();
 
-        assert_eq!(diff_single_conflict(source, &[change_line,remove_line], false),None,"If one file tries to remove a line and another wants to change it, these are not compatible changes");
+        assert_eq!(diff_single_conflict(source, &[change_line,remove_line]),None,"If one file tries to remove a line and another wants to change it, these are not compatible changes");
+    }
+
+    #[test]
+    fn test_hunk_counts() {
+        let source = "OR = \r\n{\r\n\ttier = KING\r\n\tcash = 240\r\n\treligion = rustacean\r\n}\r\n".to_owned();
+        let change_tier_1 = "OR = \r\n{\r\n\ttier = DUKE\r\n\tcash = 240\r\n\treligion = rustacean\r\n}\r\n".to_owned();
+        let change_tier_2 = "OR = \r\n{\r\n\ttier = COUNT\r\n\tcash = 240\r\n\treligion = rustacean\r\n}\r\n".to_owned();
+
+        let outcome = diff_single_conflict_detailed(&source, &[change_tier_1.clone(),change_tier_2]);
+        assert_eq!(outcome.text, None);
+        assert_eq!((outcome.hunks_merged,outcome.hunks_conflicted), (1,1));
+
+        let outcome = diff_single_conflict_detailed(&source, &[change_tier_1]);
+        assert!(outcome.text.is_some());
+        assert_eq!((outcome.hunks_merged,outcome.hunks_conflicted), (1,0));
+    }
+
+    #[test]
+    fn test_mixed_line_endings() {
+        let source = "OR = \r\n{\r\n\ttier = KING\r\n\tcash = 240\r\n}\r\n";
+        let windows = "OR = \r\n{\r\n\ttier = DUKE\r\n\tcash = 240\r\n}\r\n".to_owned();
+        let unix = windows.replace("\r\n", "\n");
+
+        // A file with Unix line endings has the same lines as its Windows twin, rather than a single long one
+        let outcome = diff_single_conflict_detailed(source, std::slice::from_ref(&unix));
+        assert_eq!(outcome, diff_single_conflict_detailed(source, &[windows]));
+        assert_eq!((outcome.hunks_merged,outcome.hunks_conflicted), (1,0));
+        assert_eq!(diff_single_conflict_detailed(&source.replace("\r\n", "\n"), &[unix]).text, outcome.text);
+    }
+
+    #[test]
+    fn test_hunk_offset_and_fuzz() {
+        let lines: Vec<String> = (1..=16).map(|i| format!("key_{} = {}",i,i)).collect();
+        let source = lines.join("\r\n");
+        let mut insert_top = lines.clone();
+        insert_top.splice(1..1, vec!["new_a = 1".to_owned(),"new_b = 2".to_owned(),"new_c = 3".to_owned()]);
+        let mut change_late = lines.clone();
+        change_late[11] = "key_12 = changed".to_owned();
+        let mut change_near = lines.clone();
+        change_near[9] = "key_10 = changed".to_owned();
+
+        let outcome = diff_single_conflict_detailed(&source, &[insert_top.join("\r\n"),change_late.join("\r\n"),change_near.join("\r\n")]);
+        assert!(outcome.text.unwrap().contains("new_c = 3\r\nkey_2 = 2"));
+        let placed: Vec<(usize,usize,bool,i64,usize)> = outcome.hunks.iter().map(|h| (h.version,h.line,h.applied,h.offset,h.fuzz)).collect();
+        // The last two hunks moved down by the inserted lines, and each has a context line the other changes
+        assert_eq!(placed, vec![(0,2,true,0,0),(1,12,true,3,1),(2,10,true,3,1)]);
+    }
+
+    #[test]
+    fn test_context_and_comparison_settings() {
+        let source = "trait = {\r\n\tdiplomacy = 1\r\n\tmartial = 1\r\n}\r\n";
+        let reindented = "trait = {\r\n    diplomacy = 1\r\n    martial = 1\r\n}\r\n".to_owned();
+        let commented = "trait = {\r\n\tdiplomacy = 1 # was 2\r\n\tmartial = 1\r\n}\r\n".to_owned();
+        let changed = "trait = {\r\n\tdiplomacy = 3\r\n\tmartial = 1\r\n}\r\n".to_owned();
+        let merge = |first: &String, options: DiffOptions| diff_single_conflict_with(source, &[first.clone(),changed.clone()], &options).text;
+        let defaults = DiffOptions::default();
+
+        // Changes the comparison leaves out do not clash, and lines are written as the mod changing them has them
+        assert_eq!(merge(&reindented, defaults), None);
+        assert_eq!(merge(&reindented, DiffOptions {ignore_indentation: true, ..defaults}), Some(changed.clone()));
+        assert_eq!(merge(&commented, defaults), Some(changed.clone()));
+        assert_eq!(merge(&commented, DiffOptions {ignore_comments: false, ..defaults}), None);
+
+        // A change further away than the context lines does not count towards the fuzz
+        let lines: Vec<String> = (1..=12).map(|i| format!("key_{} = {}",i,i)).collect();
+        let mut first = lines.clone();
+        first[2] = "key_3 = changed".to_owned();
+        let mut second = lines.clone();
+        second[6] = "key_7 = changed".to_owned();
+        let fuzz = |context_lines: usize| diff_single_conflict_with(&lines.join("\r\n"), &[first.join("\r\n"),second.join("\r\n")], &DiffOptions {context_lines, ..defaults}).hunks[0].fuzz;
+        assert_eq!((fuzz(4),fuzz(3)), (1,0));
+    }
+
+    #[test]
+    fn test_misplaced_hunk_refused_and_partial_kept() {
+        let lines: Vec<String> = (1..=20).map(|i| format!("key_{} = {}",i,i)).collect();
+        let source = lines.join("\r\n");
+        let mut rewrite_block = lines.clone();
+        for line in rewrite_block.iter_mut().take(8).skip(4) {
+            *line = line.replace("key", "other");
+        }
+        let mut two_changes = lines.clone();
+        two_changes[8] = "key_9 = changed".to_owned();
+        two_changes[18] = "key_19 = changed".to_owned();
+
+        // Every context line before the hunk changing line 9 is rewritten by the first mod, so it is refused rather than guessed at
+        let outcome = diff_single_conflict_detailed(&source, &[rewrite_block.join("\r\n"),two_changes.join("\r\n")]);
+        assert_eq!(outcome.text, None);
+        assert_eq!((outcome.hunks_merged,outcome.hunks_conflicted), (2,1));
+        let refused: Vec<(usize,usize)> = outcome.hunks.iter().filter(|h| !h.applied).map(|h| (h.version,h.line)).collect();
+        assert_eq!(refused, vec![(1,9)]);
+
+        let partial = outcome.partial.unwrap();
+        assert!(partial.contains("other_5 = 5") && partial.contains("key_19 = changed"));
+        assert!(partial.contains("key_9 = 9"));
+        assert_eq!((outcome.conflicts.len(),&outcome.conflicts[0]), (1,&(9..10)));
+    }
+
+    // Every order of the given items, by Heap's algorithm
+    fn permutations<T: Clone>(items: &[T]) -> Vec<Vec<T>> {
+        fn permute<T: Clone>(items: &mut Vec<T>, size: usize, output: &mut Vec<Vec<T>>) {
+            if size <= 1 {
+                output.push(items.clone());
+                return;
+            }
+            for i in 0..size {
+                permute(items, size - 1, output);
+                let swap = if size.is_multiple_of(2) { i } else { 0 };
+                items.swap(swap, size - 1);
+            }
+        }
+        let mut output = Vec::new();
+        permute(&mut items.to_vec(), items.len(), &mut output);
+        output
     }
-}
\ No newline at end of file
+
+    // A copy of the base lines with a few random lines changed, removed or added
+    fn random_edits(base: &[String], rng: &mut Lcg, version: usize) -> String {
+        let mut lines = base.to_vec();
+        for edit in 0..1 + rng.below(2) {
+            let at = rng.below(lines.len());
+            match rng.below(3) {
+                0 => lines[at] = format!("changed_{}_{} = yes",version,edit),
+                1 => { lines.remove(at); },
+                _ => lines.insert(at, format!("added_{}_{} = yes",version,edit)),
+            }
+        }
+        lines.join("\r\n")
+    }
+
+    #[test]
+    fn test_merge_ignores_mod_order() {
+        let base: Vec<String> = (1..=60).map(|i| format!("key_{} = {}",i,i)).collect();
+        let source = base.join("\r\n");
+        let mut merged_cleanly = 0;
+        for seed in 0..40 {
+            let mut rng = Lcg(seed);
+            let mods: Vec<String> = (0..4).map(|version| random_edits(&base, &mut rng, version)).collect();
+            let first = diff_single_conflict_detailed(&source, &mods);
+            merged_cleanly += first.text.is_some() as usize;
+            for order in permutations(&mods) {
+                let outcome = diff_single_conflict_detailed(&source, &order);
+                assert_eq!(outcome.text, first.text, "seed {} merged differently in another order", seed);
+                assert_eq!(outcome.conflicts, first.conflicts, "seed {} clashed differently in another order", seed);
+            }
+        }
+        assert!(merged_cleanly > 0 && merged_cleanly < 40, "only {} of the random merges succeeded", merged_cleanly);
+    }
+
+    // A script file in the style of the games' event files, with many lines repeated from block to block
+    fn event_script(events: usize) -> Vec<String> {
+        let mut lines = vec!["namespace = test".to_owned()];
+        for id in 1..=events {
+            lines.extend(vec![
+                "character_event = {".to_owned(),
+                format!("\tid = test.{}",id),
+                format!("\tdesc = EVTDESC_test_{}",id),
+                "\ttrigger = {".to_owned(),
+                "\t\tis_adult = yes".to_owned(),
+                "\t}".to_owned(),
+                "\toption = {".to_owned(),
+                format!("\t\tname = EVTOPTA_test_{}",id),
+                "\t\tprestige = 1".to_owned(),
+                "\t}".to_owned(),
+                "}".to_owned(),
+            ]);
+        }
+        lines
+    }
+
+    // Gives every few lines of the base to at most one mod and makes a random edit in its middle, so no two mods' edits are near each other
+    // Returns every mod's version and the base with every edit made
+    fn random_separate_edits(base: &[String], mods: usize, rng: &mut Lcg) -> (Vec<String>,Vec<String>) {
+        const SLOT: usize = 12;
+        let mut versions = vec![base.to_vec(); mods];
+        let mut expected = base.to_vec();
+        // From the end, so earlier slots keep their positions
+        for slot in (0..base.len() / SLOT).rev() {
+            let owner = rng.below(mods + 1);
+            if owner == mods {
+                continue;
+            }
+            let at = slot * SLOT + SLOT / 2;
+            let kind = rng.below(4);
+            separate_edit(&mut versions[owner], kind, at);
+            separate_edit(&mut expected, kind, at);
+        }
+        (versions.into_iter().map(|lines| lines.join("\r\n")).collect(),expected)
+    }
+
+    fn separate_edit(lines: &mut Vec<String>, kind: usize, at: usize) {
+        match kind {
+            0 => lines[at] = format!("\tchanged_{} = yes",at),
+            1 => { lines.remove(at); },
+            2 => lines.insert(at, format!("\tadded_{} = yes",at)),
+            _ => { lines.splice(at..at + 2, vec![format!("\treplaced_{} = {{",at),"\t}".to_owned()]); },
+        }
+    }
+
+    #[test]
+    fn test_random_separate_edits_all_survive() {
+        let base = event_script(20);
+        let source = base.join("\r\n");
+        for seed in 0..30 {
+            let mut rng = Lcg(seed);
+            let mods = 2 + rng.below(3);
+            let (versions,expected) = random_separate_edits(&base, mods, &mut rng);
+            let outcome = diff_single_conflict_detailed(&source, &versions);
+            assert_eq!(outcome.hunks_conflicted, 0, "seed {} left out a hunk: {:?}", seed, outcome.hunks);
+            assert_eq!(outcome.text.unwrap(), expected.join("\r\n"), "seed {} lost an edit", seed);
+        }
+    }
+
+    #[test]
+    fn test_same_place_additions_and_same_changes() {
+        let source = "a = 1\r\nb = 2\r\nc = 3\r\nd = 4";
+        let add_x = "a = 1\r\nb = 2\r\nx = 1\r\nc = 3\r\nd = 4".to_owned();
+        let add_y = "a = 1\r\nb = 2\r\ny = 1\r\nc = 3\r\nd = 4".to_owned();
+
+        // Neither order of two additions at the same place is right, so they clash whichever mod comes first
+        let forward = diff_single_conflict_detailed(source, &[add_x.clone(),add_y.clone()]);
+        let backward = diff_single_conflict_detailed(source, &[add_y,add_x.clone()]);
+        assert_eq!(forward.text, None);
+        assert_eq!((forward.conflicts.len(),&forward.conflicts[0]), (1,&(3..3)));
+        assert_eq!(backward.conflicts, forward.conflicts);
+        assert!(forward.partial.unwrap().contains("x = 1") && backward.partial.unwrap().contains("y = 1"));
+
+        // The same change made by two mods is merged once
+        let outcome = diff_single_conflict_detailed(source, &[add_x.clone(),add_x.clone()]);
+        assert_eq!(outcome.text, Some(add_x));
+        assert_eq!(outcome.hunks.iter().map(|h| h.offset).collect::<Vec<i64>>(), vec![0,0]);
+    }
+
+    #[test]
+    #[ignore = "merges 1.2 million lines, run with --ignored"]
+    fn test_more_distinct_lines_than_characters() {
+        // Each distinct line used to be encoded as a character, and there are fewer of those than lines here
+        let lines: Vec<String> = (0..1_200_000).map(|i| format!("c_{} = {{ dynasty = {} }}",i,i)).collect();
+        let source = lines.join("\r\n");
+        let mut first = lines.clone();
+        first[10] = "c_10 = { dynasty = 1 }".to_owned();
+        let mut last = lines.clone();
+        last[1_199_990] = "c_1199990 = { dynasty = 2 }".to_owned();
+
+        let outcome = diff_single_conflict_detailed(&source, &[first.join("\r\n"),last.join("\r\n")]);
+        assert_eq!((outcome.hunks_merged,outcome.hunks_conflicted), (2,0));
+        let merged = outcome.text.unwrap();
+        assert!(merged.contains("c_10 = { dynasty = 1 }") && merged.contains("c_1199990 = { dynasty = 2 }"));
+    }
+
+    #[test]
+    fn test_merge_lines() {
+        let base = "a\nb\nc\nd\ne\n";
+        let mods = vec!["A\nb\nc\nd\nE\n".to_owned(), "a\nb\nC\nd\nE\n".to_owned(), "a\nb\nc2\nd\ne\n".to_owned()];
+        let chunks = merge_lines(base, &mods);
+        let conflict = ConflictHunk {
+            base_start: 2,
+            base: vec!["c".to_owned()],
+            versions: vec![None, Some(vec!["C".to_owned()]), Some(vec!["c2".to_owned()])],
+        };
+        assert_eq!(chunks, vec![
+            MergeChunk::Clean(vec!["A".to_owned(),"b".to_owned()]),
+            MergeChunk::Conflict(conflict.clone()),
+            MergeChunk::Clean(vec!["d".to_owned(),"E".to_owned()]),
+        ]);
+        assert_eq!(conflict.all_in_order(), vec!["C","c2"]);
+
+        // Insertions at the same place by two mods clash, unless they insert the same lines
+        let chunks = merge_lines("a\nb\n", &["a\nx\nb\n".to_owned(), "a\ny\nb\n".to_owned()]);
+        assert!(matches!(&chunks[1], MergeChunk::Conflict(hunk) if hunk.base.is_empty()));
+        let chunks = merge_lines("a\nb\n", &["a\nx\nb\n".to_owned(), "a\nx\nb\n".to_owned()]);
+        assert_eq!(chunks, vec![MergeChunk::Clean(vec!["a".to_owned(),"x".to_owned(),"b".to_owned()])]);
+    }
+
+    #[test]
+    fn test_old_settings_map_onto_new_ones() {
+        let old: DiffSettings = toml::from_str("match_threshold = 0.22\nmatch_bits = 8\nmatch_distance = 1000\ndelete_threshold = 0.5\n").unwrap();
+        assert_eq!(old.ignored(), vec!["match_distance","delete_threshold"]);
+        let options = DiffOptions::from(old);
+        assert_eq!((options.context_lines,options.max_fuzz), (8,2));
+
+        let mixed: DiffOptions = toml::from_str("max_fuzz = 1\nmatch_threshold = 0.9\nmatch_bits = 8\ncontext_lines = 3\n").unwrap();
+        assert_eq!((mixed.context_lines,mixed.max_fuzz), (3,1), "The new settings win over the old ones");
+        let empty: DiffOptions = toml::from_str("").unwrap();
+        assert_eq!(empty, DiffOptions::default());
+    }
+}
@@ -1,3 +1,5 @@
+pub mod conflict_matrix;
 pub mod mod_conflict;
 pub mod mod_info;
-pub mod mod_pack;
\ No newline at end of file
+pub mod mod_pack;
+pub mod mod_reader;
\ No newline at end of file
@@ -0,0 +1,140 @@
+use std::path::{Path,PathBuf};
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use super::mod_conflict::ModConflict;
+
+/// Every file shared by a pair of mods
+#[derive(Serialize,Clone,Debug,PartialEq,Eq)]
+pub struct ModPairConflicts {
+    /// The mod that loads first of the pair
+    pub first: String,
+    /// The mod that loads last of the pair
+    pub second: String,
+    pub files: Vec<PathBuf>,
+    /// Whether every shared file is in a folder the config marks as unmergeable
+    pub all_unmergeable: bool,
+}
+
+/// Counts of the files shared by each pair of mods in a mod pack
+#[derive(Serialize,Clone,Debug,Default,PartialEq,Eq)]
+pub struct ConflictMatrix {
+    /// Every mod with at least one conflict, in load order
+    pub mods: Vec<String>,
+    /// Every pair of mods sharing at least one file, ordered by the load order of both mods
+    pub pairs: Vec<ModPairConflicts>,
+}
+
+impl ConflictMatrix {
+    /// Builds the matrix from a list of conflicts
+    /// # Arguments
+    ///
+    /// * `load_order` - names of every mod, in load order
+    ///
+    /// * `conflicts` - conflicting files and the mods which contain them
+    ///
+    /// * `unmergeable_paths` - folders whose files can never be merged
+    pub fn from_conflicts(load_order: &[String], conflicts: &[ModConflict], unmergeable_paths: &[PathBuf]) -> Self {
+        let position: HashMap<&str,usize> = load_order.iter().enumerate().map(|(idx,name)| (name.as_str(),idx)).collect();
+        let mut shared: HashMap<(usize,usize),Vec<PathBuf>> = HashMap::new();
+
+        for conflict in conflicts {
+            let mut indices: Vec<usize> = conflict.list_mods().iter().filter_map(|name| position.get(name.as_str()).copied()).collect();
+            indices.sort_unstable();
+            indices.dedup();
+            for (i,first) in indices.iter().enumerate() {
+                for second in &indices[i+1..] {
+                    shared.entry((*first,*second)).or_default().push(conflict.path().to_path_buf());
+                }
+            }
+        }
+
+        let mut keys: Vec<(usize,usize)> = shared.keys().copied().collect();
+        keys.sort_unstable();
+        let mut involved: Vec<usize> = keys.iter().flat_map(|(a,b)| vec![*a,*b]).collect();
+        involved.sort_unstable();
+        involved.dedup();
+
+        let pairs = keys.into_iter().map(|key| {
+            let mut files = shared.remove(&key).unwrap_or_default();
+            files.sort();
+            let all_unmergeable = files.iter().all(|file| unmergeable_paths.iter().any(|folder| file.starts_with(folder)));
+            ModPairConflicts {first: load_order[key.0].clone(), second: load_order[key.1].clone(), files, all_unmergeable}
+        }).collect();
+
+        ConflictMatrix {mods: involved.into_iter().map(|idx| load_order[idx].clone()).collect(), pairs}
+    }
+
+    /// Finds the files shared by two mods, in either order
+    pub fn pair(&self, mod_a: &str, mod_b: &str) -> Option<&ModPairConflicts> {
+        self.pairs.iter().find(|pair| (pair.first == mod_a && pair.second == mod_b) || (pair.first == mod_b && pair.second == mod_a))
+    }
+
+    /// Number of files shared by two mods
+    pub fn count(&self, mod_a: &str, mod_b: &str) -> usize {
+        self.pair(mod_a, mod_b).map(|pair| pair.files.len()).unwrap_or(0)
+    }
+
+    /// Lists the files shared by two mods, empty if they do not conflict
+    pub fn files(&self, mod_a: &str, mod_b: &str) -> &[PathBuf] {
+        match self.pair(mod_a, mod_b) {
+            Some(pair) => &pair.files,
+            None => &[],
+        }
+    }
+
+    pub fn to_json(&self) -> Result<String,serde_json::Error> {
+        serde_json::to_string_pretty(self)
+    }
+
+    /// One row per conflicting pair, with the shared files separated by semicolons
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("first,second,shared_files,all_unmergeable,files\n");
+        for pair in &self.pairs {
+            let files: Vec<String> = pair.files.iter().map(|file| path_string(file)).collect();
+            csv.push_str(&format!("{},{},{},{},{}\n",
+                csv_field(&pair.first),
+                csv_field(&pair.second),
+                pair.files.len(),
+                pair.all_unmergeable,
+                csv_field(&files.join(";"))));
+        }
+        csv
+    }
+}
+
+fn path_string(path: &Path) -> String {
+    path.to_string_lossy().replace('\\', "/")
+}
+
+// Quote fields containing separators, doubling any quotes inside them
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"",field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_pairs_follow_load_order() {
+        let load_order = vec!["A".to_owned(), "B, the mod".to_owned(), "C".to_owned()];
+        let conflicts = vec![
+            ModConflict::new(PathBuf::from("common/a.txt"), &["C".to_owned(), "A".to_owned()]),
+            ModConflict::new(PathBuf::from("gfx/b.gfx"), &["A".to_owned(), "B, the mod".to_owned(), "C".to_owned()]),
+        ];
+        let matrix = ConflictMatrix::from_conflicts(&load_order, &conflicts, &[PathBuf::from("gfx")]);
+
+        assert_eq!(matrix.mods, load_order);
+        assert_eq!(matrix.count("C", "A"), 2);
+        assert_eq!(matrix.files("A", "B, the mod"), &[PathBuf::from("gfx/b.gfx")]);
+        assert!(!matrix.pair("A", "C").unwrap().all_unmergeable);
+        assert!(matrix.pair("B, the mod", "C").unwrap().all_unmergeable);
+        assert_eq!(matrix.to_csv().lines().nth(1), Some("A,\"B, the mod\",1,true,gfx/b.gfx"));
+    }
+}
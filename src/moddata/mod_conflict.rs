@@ -1,11 +1,16 @@
 use std::path::{Path,PathBuf};
 use std::collections::{HashMap};
 
+use serde::Serialize;
+
 use super::mod_info::ModInfo;
+use crate::progress::{ProgressEvent,ProgressReporter,ProgressStage};
 
-#[derive(Clone,Debug)]
+#[derive(Serialize,Clone,Debug)]
 pub struct ModConflict {
+    #[serde(rename = "path")]
     file_path: PathBuf,
+    #[serde(rename = "mods")]
     mod_names: Vec<String>,
 }
 
@@ -14,12 +19,15 @@ impl ModConflict {
         ModConflict{ file_path: path, mod_names: mods.to_vec()}
     }
 
-    pub fn compare_mods(mod_list: &[ModInfo],valid_paths: Option<&Vec<PathBuf>>, valid_extensions: Option<&Vec<String>>) -> Vec<ModConflict> {
+    pub fn compare_mods(mod_list: &[ModInfo],valid_paths: Option<&Vec<PathBuf>>, valid_extensions: Option<&Vec<String>>, progress: &dyn ProgressReporter) -> Vec<ModConflict> {
+        let stage = ProgressStage::Conflicts;
         let mut out = Vec::new();
         let mut conflicts: HashMap<String,ModConflict> = HashMap::new();
 
+        progress.report(ProgressEvent::Started { stage, total: mod_list.iter().filter(|mod_info| mod_info.get_active()).count() });
         for mod_info in mod_list {
             if mod_info.get_active() {
+            progress.report(ProgressEvent::Processed { stage, path: mod_info.get_mod_path(), mod_name: Some(mod_info.get_name()) });
             for file_path in mod_info.get_filetree() {
                 let mut file_path = file_path.to_string();
                 file_path.make_ascii_lowercase();
@@ -53,6 +61,9 @@ impl ModConflict {
                 out.push(conf.1);
             }
         }
+        // Keep conflicts in a stable order regardless of hash map iteration
+        out.sort_by(|a,b| a.file_path.cmp(&b.file_path));
+        progress.report(ProgressEvent::Finished { stage });
 
         out
     }
@@ -10,13 +10,14 @@ pub struct ModInfo {
     dependencies: Vec<String>,
     replacement_paths: Vec<PathBuf>,
     user_dir: Option<String>,
+    version: Option<String>,
     enabled: bool,
 }
 
 impl ModInfo {
     pub fn new(mod_path: PathBuf, file_list: &[&str], data_path: PathBuf, name: String, dependencies: &[String], replacement_paths: &[PathBuf], user_dir: Option<String>, enabled: bool) -> ModInfo {
         let file_tree = ModInfo::list_to_tree(file_list);
-        ModInfo {mod_path,file_tree,data_path,name,dependencies: dependencies.to_vec(),replacement_paths: replacement_paths.to_vec(), user_dir, enabled}
+        ModInfo {mod_path,file_tree,data_path,name,dependencies: dependencies.to_vec(),replacement_paths: replacement_paths.to_vec(), user_dir, version: None, enabled}
     }
 
     fn list_to_tree(list: &[&str]) -> HashSet<String> {
@@ -31,7 +32,7 @@ impl ModInfo {
     pub fn empty(mod_path: PathBuf, data_path: PathBuf, name: String) -> ModInfo {
         let dependencies = Vec::new();
         let replacement_paths = Vec::new();
-        ModInfo{mod_path,file_tree: HashSet::new(),data_path,name,dependencies,replacement_paths,user_dir: None, enabled: false}
+        ModInfo{mod_path,file_tree: HashSet::new(),data_path,name,dependencies,replacement_paths,user_dir: None, version: None, enabled: false}
     }
 
     pub fn is_zip(&self) -> bool {
@@ -71,6 +72,10 @@ impl ModInfo {
         &self.user_dir
     }
 
+    pub fn get_version(&self) -> &Option<String> {
+        &self.version
+    }
+
     pub fn enable(&mut self) {
         self.enabled = true;
     }
@@ -92,4 +97,10 @@ impl ModInfo {
         new_info.enabled = enabled;
         new_info
     }
+
+    pub fn with_version(self,version: Option<String>) -> Self {
+        let mut new_info = self;
+        new_info.version = version;
+        new_info
+    }
 }
\ No newline at end of file
@@ -2,9 +2,12 @@ use std::path::{Path,PathBuf};
 use std::collections::{HashMap};
 
 use fxhash::hash64;
+use serde::Serialize;
 
 use super::mod_info::ModInfo;
 use super::mod_conflict::ModConflict;
+use super::conflict_matrix::ConflictMatrix;
+use crate::progress::ProgressReporter;
 
 #[derive(Clone,Debug)]
 pub struct ModPack {
@@ -18,11 +21,16 @@ pub struct ModPack {
     noncrypto_hash: u64,
 }
 
-#[derive(Clone,Debug)]
+#[derive(Serialize,Clone,Debug)]
 pub struct ModStatus {
+    #[serde(rename = "enabled")]
     active: bool,
+    #[serde(rename = "name")]
     mod_name: String,
+    // Only meaningful to the mod pack that handed it out
+    #[serde(skip)]
     special_number: ModToken,
+    #[serde(rename = "descriptor")]
     file_path: PathBuf,
 }
 
@@ -71,7 +79,7 @@ impl ModPack {
         self
     }
 
-    pub fn add_mods(&mut self,mods: &[ModInfo], regen: bool, filter_vanilla: bool) {
+    pub fn add_mods(&mut self,mods: &[ModInfo], regen: bool, filter_vanilla: bool, progress: &dyn ProgressReporter) {
         for mod_info in mods {
             if let Some(existing) = self.mod_lookup.get(mod_info.get_name()) {
                 self.mod_list[*existing] = mod_info.clone();
@@ -85,7 +93,7 @@ impl ModPack {
         self.resort_by_dependencies();
 
         if regen || filter_vanilla {
-            self.generate_conflicts();
+            self.generate_conflicts(progress);
             if filter_vanilla {
                 let mut i = 0;
                 while i != self.conflicts.len() {
@@ -136,7 +144,7 @@ impl ModPack {
         self.mod_lookup = no_deps_hash;
     }
 
-    pub fn generate_conflicts(&mut self) {
+    pub fn generate_conflicts(&mut self, progress: &dyn ProgressReporter) {
         self.conflicts = ModConflict::compare_mods(
             &self.mod_list, 
             if self.valid_paths.is_empty() {
@@ -148,7 +156,8 @@ impl ModPack {
                 None
             } else {
                 Some(&self.valid_extensions)
-            }
+            },
+            progress
         );
         self.conflict_lookup.clear();
         for (i,conf) in self.conflicts.iter().enumerate() {
@@ -169,6 +178,15 @@ impl ModPack {
         &self.conflicts
     }
 
+    /// Counts the files shared by every pair of active mods
+    /// # Arguments
+    ///
+    /// * `unmergeable_paths` - folders whose files can never be merged, pairs conflicting only in these are flagged
+    pub fn conflict_matrix(&self, unmergeable_paths: &[PathBuf]) -> ConflictMatrix {
+        let load_order: Vec<String> = self.mod_list.iter().filter(|m| m.get_active()).map(|m| m.get_name().to_owned()).collect();
+        ConflictMatrix::from_conflicts(&load_order, &self.conflicts, unmergeable_paths)
+    }
+
     pub fn load_order(&self) -> Vec<ModStatus> {
         let mut out = Vec::new();
         for (idx,file) in self.mod_list.iter().enumerate() {
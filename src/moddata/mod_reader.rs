@@ -0,0 +1,135 @@
+use std::path::{Path,PathBuf};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::sync::{Arc,Mutex};
+
+use zip::read::ZipArchive;
+
+use super::mod_info::ModInfo;
+use crate::io::{encodings,zips};
+use crate::io::encodings::DecodedText;
+
+/// An opened mod archive along with an index of its entries by lowercase name
+struct CachedArchive {
+    archive: ZipArchive<BufReader<File>>,
+    entries: HashMap<String,String>,
+}
+
+/// Reads files out of mod folders and zip archives
+/// Every archive is opened and indexed once, then shared by all later reads, including reads from other threads
+#[derive(Default)]
+pub struct ModReader {
+    archives: Mutex<HashMap<PathBuf,Arc<Mutex<CachedArchive>>>>,
+    folders: Mutex<HashMap<PathBuf,Arc<HashMap<String,String>>>>,
+}
+
+impl ModReader {
+    pub fn new() -> Self {
+        ModReader::default()
+    }
+
+    /// Get the raw contents of a single file in a mod, matching the path without regard to case
+    /// #Arguments
+    ///
+    /// * `mod_entry` - mod to read from
+    ///
+    /// * `path` - relative path of the file in the mod
+    pub fn read_bytes(&self, mod_entry: &ModInfo, path: &Path) -> Result<Vec<u8>,std::io::Error> {
+        let lookup = lowercase_key(path).ok_or_else(|| not_found(mod_entry, path))?;
+        if mod_entry.is_zip() {
+            let cached = self.archive(mod_entry.get_data_path())?;
+            let mut cached = cached.lock().map_err(|_| poisoned())?;
+            let entry_name = cached.entries.get(&lookup).ok_or_else(|| not_found(mod_entry, path))?.clone();
+            zips::zip_read_entry(&mut cached.archive, &entry_name)
+        } else {
+            let index = self.folder_index(mod_entry);
+            let real_path = index.get(&lookup).ok_or_else(|| not_found(mod_entry, path))?;
+            std::fs::read(mod_entry.get_data_path().join(real_path))
+        }
+    }
+
+    /// Get the contents of a single file in a mod as text, along with the encoding it was found in
+    /// #Arguments
+    ///
+    /// * `mod_entry` - mod to read from
+    ///
+    /// * `path` - relative path of the file in the mod
+    ///
+    /// * `decode` - if yes, attempt to decode the file contents, otherwise read in bytes as-is
+    ///
+    /// * `normalize` - if yes, convert all line-endings to windows-style
+    pub fn read_text(&self, mod_entry: &ModInfo, path: &Path, decode: bool, normalize: bool) -> Result<DecodedText,std::io::Error> {
+        let bytes = self.read_bytes(mod_entry, path)?;
+        encodings::read_bytes_to_text(bytes, decode, normalize).ok_or_else(|| not_text(mod_entry, path))
+    }
+
+    /// List the real relative path of every file in a mod
+    /// Fails if the mod is an archive that can't be opened
+    /// #Arguments
+    ///
+    /// * `mod_entry` - mod to list
+    pub fn list_files(&self, mod_entry: &ModInfo) -> Result<Vec<String>,std::io::Error> {
+        let mut names: Vec<String> = if mod_entry.is_zip() {
+            let cached = self.archive(mod_entry.get_data_path())?;
+            let cached = cached.lock().map_err(|_| poisoned())?;
+            cached.entries.values().cloned().collect()
+        } else {
+            self.folder_index(mod_entry).values().cloned().collect()
+        };
+        names.sort();
+        Ok(names)
+    }
+
+    // Fetch an archive from the cache, opening and indexing it on first use
+    fn archive(&self, zip_path: &Path) -> Result<Arc<Mutex<CachedArchive>>,std::io::Error> {
+        let mut archives = self.archives.lock().map_err(|_| poisoned())?;
+        if let Some(cached) = archives.get(zip_path) {
+            return Ok(Arc::clone(cached));
+        }
+
+        let archive = zips::zip_open(zip_path)
+            .map_err(|e| std::io::Error::new(e.kind(), format!("{}: {}",zip_path.display(),e)))?;
+        let entries = archive.file_names()
+            .filter(|name| !name.ends_with('/'))
+            .filter_map(|name| Some((lowercase_key(Path::new(name))?, name.to_owned())))
+            .collect();
+        let cached = Arc::new(Mutex::new(CachedArchive {archive, entries}));
+        archives.insert(zip_path.to_path_buf(), Arc::clone(&cached));
+        Ok(cached)
+    }
+
+    // Fetch the lowercase index of a folder mod's files, building it from the mod's file tree on first use
+    fn folder_index(&self, mod_entry: &ModInfo) -> Arc<HashMap<String,String>> {
+        let mut folders = match self.folders.lock() {
+            Ok(f) => f,
+            Err(_) => return Arc::new(HashMap::new()),
+        };
+        let index = folders.entry(mod_entry.get_data_path().to_path_buf()).or_insert_with(|| {
+            let index = mod_entry.get_filetree().iter()
+                .filter_map(|name| Some((lowercase_key(Path::new(name))?, name.clone())))
+                .collect();
+            Arc::new(index)
+        });
+        Arc::clone(index)
+    }
+}
+
+fn not_found(mod_entry: &ModInfo, path: &Path) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::NotFound, format!("{} is not in {}",path.display(),mod_entry.get_name()))
+}
+
+fn not_text(mod_entry: &ModInfo, path: &Path) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, format!("{} in {} could not be read as text",path.display(),mod_entry.get_name()))
+}
+
+fn poisoned() -> std::io::Error {
+    std::io::Error::other("archive lock was poisoned")
+}
+
+// Archive entries always use forward slashes, so normalize separators along with case
+fn lowercase_key(path: &Path) -> Option<String> {
+    let mut key = path.to_str()?.replace('\\', "/");
+    key.make_ascii_lowercase();
+    Some(key)
+}
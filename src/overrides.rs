@@ -0,0 +1,158 @@
+use std::path::{Path,PathBuf};
+use std::collections::BTreeMap;
+
+use serde::{Deserialize,Serialize};
+
+use crate::io::files;
+use crate::manifest::ManifestInput;
+
+/// Name of the file in the override folder recording which inputs each override was written against
+pub const OVERRIDE_RECORD_NAME: &str = "merge_overrides.json";
+
+/// The inputs an override was written against, along with the override itself
+#[derive(Serialize,Deserialize,Clone,Debug,PartialEq,Eq)]
+pub struct OverrideRecord {
+    /// Hash of the override file, so editing it marks it as revisited
+    pub override_hash: String,
+    /// Hash of the vanilla file, None if the game has no such file
+    pub vanilla_hash: Option<String>,
+    /// Every enabled mod's version of the file, in load order
+    pub inputs: Vec<ManifestInput>,
+}
+
+/// Whether an override still matches the files it replaces
+#[derive(Serialize,Deserialize,Clone,Debug,PartialEq,Eq)]
+pub struct OverrideReport {
+    /// Path of the file relative to the game directory
+    pub path: PathBuf,
+    /// True if the vanilla file or any mod's version changed since the override was written
+    pub stale: bool,
+    /// What changed, `vanilla` or the name of each mod that was updated, added or removed
+    pub changed: Vec<String>,
+}
+
+/// A folder of hand-written files that always replace the merged output for the same path
+pub struct OverrideDir {
+    root: PathBuf,
+    /// Lower case path of every override relative to the game directory, mapped to the file on disk
+    files: BTreeMap<PathBuf,PathBuf>,
+    records: BTreeMap<PathBuf,OverrideRecord>,
+}
+
+impl OverrideDir {
+    /// Lists every override in a folder and reads the record of what they were written against
+    pub fn open(root: &Path) -> Result<Self,Box<dyn std::error::Error>> {
+        if !root.is_dir() {
+            return Err(format!("The override folder {} does not exist",root.display()).into());
+        }
+        let files = files::walk_in_dir(root, Some(root)).into_iter()
+            .filter(|path| path != Path::new(OVERRIDE_RECORD_NAME))
+            .map(|path| (normalize(&path), root.join(path)))
+            .collect();
+        let record_path = root.join(OVERRIDE_RECORD_NAME);
+        let records = if record_path.exists() {
+            serde_json::from_str(&std::fs::read_to_string(record_path)?)?
+        } else {
+            BTreeMap::new()
+        };
+        Ok(OverrideDir {root: root.to_path_buf(), files, records})
+    }
+
+    /// Paths of every override relative to the game directory, in lower case
+    pub fn paths(&self) -> Vec<&Path> {
+        self.files.keys().map(|path| path.as_path()).collect()
+    }
+
+    /// Whether a file is replaced by an override
+    pub fn contains(&self, path: &Path) -> bool {
+        self.files.contains_key(&normalize(path))
+    }
+
+    /// Contents of the override for a file
+    pub fn read(&self, path: &Path) -> Result<Vec<u8>,std::io::Error> {
+        let file = self.files.get(&normalize(path)).ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, format!("there is no override for {}",path.display())))?;
+        files::fetch_bytes_in_path(file)
+    }
+
+    /// Compares the current inputs of an override against those it was written for
+    /// Overrides seen for the first time, or edited since they were last checked, are taken to be written against the current inputs
+    pub fn review(&mut self, path: &Path, current: OverrideRecord) -> OverrideReport {
+        let path = normalize(path);
+        let changed = match self.records.get(&path) {
+            Some(recorded) if recorded.override_hash == current.override_hash => changed_inputs(recorded, &current),
+            _ => {
+                self.records.insert(path.clone(), current);
+                Vec::new()
+            },
+        };
+        OverrideReport {path, stale: !changed.is_empty(), changed}
+    }
+
+    /// Writes the record of what every override was written against, forgetting overrides that were deleted
+    pub fn save_records(&mut self) -> Result<(),Box<dyn std::error::Error>> {
+        let files = &self.files;
+        self.records.retain(|path,_| files.contains_key(path));
+        let record_path = self.root.join(OVERRIDE_RECORD_NAME);
+        files::write_file_with_string(record_path, serde_json::to_string_pretty(&self.records)?, false)?;
+        Ok(())
+    }
+}
+
+// Overrides match conflicts regardless of case or path separators
+fn normalize(path: &Path) -> PathBuf {
+    PathBuf::from(path.to_string_lossy().replace('\\', "/").to_lowercase())
+}
+
+// Names of the inputs that differ between two records
+fn changed_inputs(recorded: &OverrideRecord, current: &OverrideRecord) -> Vec<String> {
+    let mut changed = Vec::new();
+    if recorded.vanilla_hash != current.vanilla_hash {
+        changed.push("vanilla".to_owned());
+    }
+    for input in &current.inputs {
+        if !recorded.inputs.iter().any(|old| old.mod_name == input.mod_name && old.hash == input.hash) {
+            changed.push(input.mod_name.clone());
+        }
+    }
+    for old in &recorded.inputs {
+        if !current.inputs.iter().any(|input| input.mod_name == old.mod_name) {
+            changed.push(old.mod_name.clone());
+        }
+    }
+    changed
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    fn record(override_hash: &str, mod_hash: &str) -> OverrideRecord {
+        OverrideRecord {
+            override_hash: override_hash.to_owned(),
+            vanilla_hash: Some("v".to_owned()),
+            inputs: vec![ManifestInput {mod_name: "A".to_owned(), mod_version: None, hash: mod_hash.to_owned(), encoding: Default::default(), line_ending: None}],
+        }
+    }
+
+    #[test]
+    fn test_review_overrides() {
+        let root = TempDir::new("overrides");
+        files::write_file_with_string(root.join("common/Traits.txt"), "x".to_owned(), false).unwrap();
+
+        let mut overrides = OverrideDir::open(root.path()).unwrap();
+        assert!(overrides.contains(Path::new("common/traits.txt")));
+        assert_eq!(overrides.read(Path::new("common/traits.txt")).unwrap(), b"x");
+        let path = Path::new("common/traits.txt");
+        assert!(!overrides.review(path, record("o1","m1")).stale);
+        overrides.save_records().unwrap();
+
+        // A mod update makes the override stale until the override itself is edited
+        let mut overrides = OverrideDir::open(root.path()).unwrap();
+        assert_eq!(overrides.paths(), vec![path]);
+        let report = overrides.review(path, record("o1","m2"));
+        assert_eq!((report.stale,report.changed), (true,vec!["A".to_owned()]));
+        assert!(!overrides.review(path, record("o2","m2")).stale);
+        assert!(!overrides.review(path, record("o2","m2")).stale);
+    }
+}
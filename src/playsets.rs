@@ -0,0 +1,83 @@
+use std::path::{Path,PathBuf};
+use std::collections::BTreeMap;
+
+use serde::{Deserialize,Serialize};
+
+use crate::configs;
+use crate::io::files;
+use crate::moddata::mod_pack::{ModPack,ModStatus};
+
+/// Name of the file that playsets are saved in, next to merger.toml
+pub const PLAYSETS_NAME: &str = "playsets.json";
+
+/// Named sets of enabled mods for each game, so a curated pack can be switched back on in one step
+#[derive(Serialize,Deserialize,Clone,Debug,Default,PartialEq,Eq)]
+pub struct PlaysetStore {
+    /// Game id, then playset name, then the descriptor file of every enabled mod in load order
+    games: BTreeMap<String,BTreeMap<String,Vec<PathBuf>>>,
+}
+
+impl PlaysetStore {
+    /// Default location of the playsets file, beside the user's configuration
+    pub fn default_path() -> PathBuf {
+        configs::user_config_sibling(PLAYSETS_NAME)
+    }
+
+    /// Reads the playsets file, returning an empty store if it does not exist yet
+    pub fn open(path: &Path) -> Result<Self,Box<dyn std::error::Error>> {
+        if !path.exists() {
+            return Ok(PlaysetStore::default());
+        }
+        let contents = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&contents)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(),Box<dyn std::error::Error>> {
+        files::write_file_with_string(path, serde_json::to_string_pretty(self)?, false)?;
+        Ok(())
+    }
+
+    /// Names of every playset saved for a game, in alphabetical order
+    pub fn list(&self, game_id: &str) -> Vec<&str> {
+        match self.games.get(game_id) {
+            Some(playsets) => playsets.keys().map(|name| name.as_str()).collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Descriptor files of the mods enabled by a playset
+    pub fn get(&self, game_id: &str, name: &str) -> Option<&[PathBuf]> {
+        self.games.get(game_id)?.get(name).map(|mods| mods.as_slice())
+    }
+
+    /// Saves the active mods of a load order as a playset, replacing any playset with the same name
+    pub fn insert(&mut self, game_id: &str, name: &str, load_order: &[ModStatus]) {
+        let enabled = load_order.iter().filter(|item| item.status()).map(|item| item.mod_file().to_path_buf()).collect();
+        self.games.entry(game_id.to_owned()).or_default().insert(name.to_owned(), enabled);
+    }
+
+    /// Removes a playset, returning false if there was no such playset
+    pub fn remove(&mut self, game_id: &str, name: &str) -> bool {
+        let removed = match self.games.get_mut(game_id) {
+            Some(playsets) => playsets.remove(name).is_some(),
+            None => false,
+        };
+        if self.games.get(game_id).map(|playsets| playsets.is_empty()).unwrap_or(false) {
+            self.games.remove(game_id);
+        }
+        removed
+    }
+
+    /// Enables exactly the mods in a playset, returning the descriptor files that were not found in the mod pack
+    pub fn apply(&self, game_id: &str, name: &str, mod_pack: &mut ModPack) -> Option<Vec<PathBuf>> {
+        let wanted = self.get(game_id, name)?;
+        for status in mod_pack.load_order() {
+            let should_enable = wanted.iter().any(|file| file == status.mod_file());
+            if status.status() != should_enable {
+                mod_pack.toggle_by_token(status.special_number());
+            }
+        }
+        let known = mod_pack.load_order();
+        Some(wanted.iter().filter(|file| !known.iter().any(|status| status.mod_file() == file.as_path())).cloned().collect())
+    }
+}
@@ -0,0 +1,68 @@
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize,Ordering};
+use std::thread;
+
+/// Number of worker threads to use when none are requested, one per available core
+pub fn default_workers() -> usize {
+    thread::available_parallelism().map(|n| n.get()).unwrap_or(1)
+}
+
+/// Runs `task` over every item on a pool of worker threads, returning the results in the same order as `items`
+/// Items are handed out one at a time, so slow items do not hold up the rest of a worker's share
+/// Panics if a worker panicked, rather than returning fewer results than items
+/// #Arguments
+///
+/// * `items` - work to be processed
+///
+/// * `workers` - number of threads to use, zero uses one per available core
+///
+/// * `task` - function applied to each item, called from several threads at once
+pub fn map_in_order<T, R, F>(items: &[T], workers: usize, task: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    let workers = if workers == 0 { default_workers() } else { workers };
+    let workers = workers.min(items.len());
+    if workers <= 1 {
+        return items.iter().map(task).collect();
+    }
+
+    let next_item = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<R>>> = Mutex::new(items.iter().map(|_| None).collect());
+
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| loop {
+                let idx = next_item.fetch_add(1, Ordering::Relaxed);
+                let item = match items.get(idx) {
+                    Some(item) => item,
+                    None => break,
+                };
+                let result = task(item);
+                // A worker that panicked while holding the lock never wrote anything half done, so the other results are still good
+                results.lock().unwrap_or_else(|e| e.into_inner())[idx] = Some(result);
+            });
+        }
+    });
+
+    results.into_inner().unwrap_or_else(|e| e.into_inner()).into_iter().enumerate()
+        .map(|(idx,result)| result.unwrap_or_else(|| panic!("no result for item {} of {}",idx,items.len())))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_results_keep_input_order() {
+        let items: Vec<u64> = (0..200).collect();
+        let single = map_in_order(&items, 1, |x| x * x);
+        let many = map_in_order(&items, 8, |x| x * x);
+
+        assert_eq!(single, many);
+        assert_eq!(many[199], 199 * 199);
+    }
+}
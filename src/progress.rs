@@ -0,0 +1,54 @@
+use std::path::Path;
+
+/// The long running operations that report their progress
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum ProgressStage {
+    /// Reading mod descriptors from the user directory
+    Scanning,
+    /// Comparing the file trees of all enabled mods
+    Conflicts,
+    /// Merging each conflicting file
+    Merging,
+    /// Copying mod files into the output mod
+    Extracting,
+}
+
+/// A single event emitted while scanning, detecting conflicts, merging or extracting
+#[derive(Clone,Debug)]
+pub enum ProgressEvent<'a> {
+    /// A stage has begun and expects to process `total` items
+    Started { stage: ProgressStage, total: usize },
+    /// One item of the stage has been processed, along with the mod it belongs to
+    Processed { stage: ProgressStage, path: &'a Path, mod_name: Option<&'a str> },
+    /// A conflicting file and the mods involved are about to be merged
+    Conflict { path: &'a Path, mods: &'a [String] },
+    /// Something went wrong, but the operation will continue
+    Warning { stage: ProgressStage, message: &'a str },
+    /// A stage has completed
+    Finished { stage: ProgressStage },
+}
+
+/// Receives progress events from the library, so front ends can draw progress bars or logs
+/// Events may arrive from several worker threads at once
+pub trait ProgressReporter: Sync {
+    fn report(&self, event: ProgressEvent);
+
+    /// Shorthand for reporting a warning message
+    fn warn(&self, stage: ProgressStage, message: &str) {
+        self.report(ProgressEvent::Warning { stage, message });
+    }
+}
+
+/// Reporter that discards every event
+#[derive(Clone,Copy,Debug,Default)]
+pub struct SilentProgress;
+
+impl ProgressReporter for SilentProgress {
+    fn report(&self, _event: ProgressEvent) {}
+}
+
+impl<F: Fn(ProgressEvent) + Sync> ProgressReporter for F {
+    fn report(&self, event: ProgressEvent) {
+        self(event)
+    }
+}
@@ -0,0 +1,214 @@
+use std::path::PathBuf;
+
+use serde::{Deserialize,Serialize};
+
+use crate::manifest::MergeStrategy;
+use crate::overrides::OverrideReport;
+
+/// What happened to a single conflicting file
+#[derive(Serialize,Deserialize,Clone,Copy,Debug,PartialEq,Eq)]
+pub enum MergeOutcome {
+    /// Every mod's changes were merged into one file
+    Merged,
+    /// The inputs were unchanged since the last patch, so its output was reused
+    Reused,
+    /// The changes clash, every version was written out for manual merging
+    NeedsManualMerge,
+    /// The file merged, but the result could not be written
+    WriteFailed,
+    /// A hand-written override replaced the file, so it was not merged
+    Overridden,
+}
+
+impl MergeOutcome {
+    /// Whether the patch contains a usable merged file
+    pub fn is_success(self) -> bool {
+        matches!(self, MergeOutcome::Merged | MergeOutcome::Reused | MergeOutcome::Overridden)
+    }
+}
+
+/// Details of how a single conflicting file was merged
+#[derive(Serialize,Deserialize,Clone,Debug,PartialEq,Eq)]
+pub struct ConflictReport {
+    /// Path of the file relative to the game directory
+    pub path: PathBuf,
+    /// Every mod containing the file, in load order
+    pub mods: Vec<String>,
+    pub strategy: MergeStrategy,
+    pub outcome: MergeOutcome,
+    /// Hunks that applied cleanly, None if the output was reused from the last patch
+    pub hunks_merged: Option<usize>,
+    /// Hunks that clashed with another mod's changes, None if the output was reused from the last patch
+    pub hunks_conflicted: Option<usize>,
+    /// Where the output was written, relative to the patch's output directory
+    pub output: PathBuf,
+    /// Unified diff of the merged file against vanilla, only kept by a dry run
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub diff: Option<String>,
+    /// Where every hunk of the automatic merge went, empty if it did not run
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hunks: Vec<HunkReport>,
+}
+
+/// Where the automatic merge placed a single hunk of one mod's changes
+#[derive(Serialize,Deserialize,Clone,Debug,PartialEq,Eq)]
+pub struct HunkReport {
+    #[serde(rename = "mod")]
+    pub mod_name: String,
+    /// Line of the vanilla file the hunk changes, counting from one
+    pub line: usize,
+    /// False if the hunk was refused, because an earlier mod changed the same lines or too much of its context
+    pub applied: bool,
+    /// Lines other mods added or removed before the hunk, moving it from where it is in its mod's version
+    pub offset: i64,
+    /// Most context lines on either side of the hunk that other mods change
+    pub fuzz: usize,
+}
+
+/// Report of every conflict handled while generating a patch, in conflict order
+#[derive(Serialize,Deserialize,Clone,Debug,Default,PartialEq,Eq)]
+pub struct MergeReport {
+    pub patch_name: String,
+    pub conflicts: Vec<ConflictReport>,
+    /// Every file taken from the override folder, whether or not mods conflict over it
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub overrides: Vec<OverrideReport>,
+}
+
+impl MergeReport {
+    pub fn new(patch_name: &str, conflicts: Vec<ConflictReport>) -> Self {
+        MergeReport {patch_name: patch_name.to_owned(), conflicts, overrides: Vec::new()}
+    }
+
+    pub fn with_overrides(mut self, overrides: Vec<OverrideReport>) -> Self {
+        self.overrides = overrides;
+        self
+    }
+
+    /// Overrides whose vanilla file or mods changed since they were written
+    pub fn stale_overrides(&self) -> Vec<&OverrideReport> {
+        self.overrides.iter().filter(|item| item.stale).collect()
+    }
+
+    /// Number of conflicts with a usable merged file
+    pub fn successful(&self) -> u32 {
+        self.conflicts.iter().filter(|c| c.outcome.is_success()).count() as u32
+    }
+
+    pub fn to_json(&self) -> Result<String,serde_json::Error> {
+        serde_json::to_string_pretty(self)
+    }
+
+    pub fn from_json(contents: &str) -> Result<Self,serde_json::Error> {
+        serde_json::from_str(contents)
+    }
+
+    /// Renders the report as a standalone HTML page with no external resources
+    pub fn to_html(&self) -> String {
+        let mut html = String::new();
+        html.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+        html.push_str(&format!("<title>Merge report: {}</title>\n",escape_html(&self.patch_name)));
+        html.push_str("<style>\n\
+            body { font-family: sans-serif; margin: 2em; }\n\
+            table { border-collapse: collapse; width: 100%; }\n\
+            th, td { border: 1px solid #ccc; padding: 4px 8px; text-align: left; vertical-align: top; }\n\
+            th { background: #eee; }\n\
+            .Merged, .Reused, .Overridden { background: #e6f4e6; }\n\
+            .stale { background: #fff4d6; }\n\
+            .NeedsManualMerge { background: #fff4d6; }\n\
+            .WriteFailed { background: #fbe3e3; }\n\
+            </style>\n</head>\n<body>\n");
+        html.push_str(&format!("<h1>Merge report: {}</h1>\n",escape_html(&self.patch_name)));
+        html.push_str(&format!("<p>{} of {} conflicting files merged successfully.</p>\n",self.successful(),self.conflicts.len()));
+        html.push_str("<table>\n<tr><th>File</th><th>Mods</th><th>Strategy</th><th>Outcome</th><th>Hunks merged</th><th>Hunks in conflict</th><th>Output</th></tr>\n");
+        for conflict in &self.conflicts {
+            let mods: Vec<String> = conflict.mods.iter().map(|m| escape_html(m)).collect();
+            html.push_str(&format!("<tr class=\"{:?}\"><td>{}</td><td>{}</td><td>{:?}</td><td>{:?}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+                conflict.outcome,
+                escape_html(&conflict.path.to_string_lossy()),
+                mods.join("<br>"),
+                conflict.strategy,
+                conflict.outcome,
+                count_cell(conflict.hunks_merged),
+                count_cell(conflict.hunks_conflicted),
+                escape_html(&conflict.output.to_string_lossy())));
+            let moved: Vec<String> = conflict.hunks.iter()
+                .filter(|hunk| !hunk.applied || hunk.offset != 0 || hunk.fuzz > 0)
+                .map(|hunk| match hunk.applied {
+                    true => format!("{} line {}: offset {}, fuzz {}",escape_html(&hunk.mod_name),hunk.line,hunk.offset,hunk.fuzz),
+                    false => format!("{} line {}: refused",escape_html(&hunk.mod_name),hunk.line),
+                })
+                .collect();
+            if !moved.is_empty() {
+                html.push_str(&format!("<tr><td colspan=\"7\"><details><summary>Hunks that moved or were refused</summary>{}</details></td></tr>\n",moved.join("<br>")));
+            }
+            if let Some(diff) = &conflict.diff {
+                html.push_str(&format!("<tr><td colspan=\"7\"><details><summary>Diff against vanilla</summary><pre>{}</pre></details></td></tr>\n",escape_html(diff)));
+            }
+        }
+        html.push_str("</table>\n");
+        if !self.overrides.is_empty() {
+            html.push_str("<h2>Overrides</h2>\n<table>\n<tr><th>File</th><th>Changed since the override was written</th></tr>\n");
+            for item in &self.overrides {
+                let changed: Vec<String> = item.changed.iter().map(|c| escape_html(c)).collect();
+                html.push_str(&format!("<tr class=\"{}\"><td>{}</td><td>{}</td></tr>\n",
+                    if item.stale { "stale" } else { "Overridden" },
+                    escape_html(&item.path.to_string_lossy()),
+                    if item.stale { changed.join("<br>") } else { "-".to_owned() }));
+            }
+            html.push_str("</table>\n");
+        }
+        html.push_str("</body>\n</html>\n");
+        html
+    }
+}
+
+fn count_cell(count: Option<usize>) -> String {
+    match count {
+        Some(c) => c.to_string(),
+        None => "-".to_owned(),
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_report_round_trip_and_html() {
+        let report = MergeReport::new("Patch <1>", vec![ConflictReport {
+            path: PathBuf::from("common/traits.txt"),
+            mods: vec!["A & B".to_owned(), "C".to_owned()],
+            strategy: MergeStrategy::Manual,
+            outcome: MergeOutcome::NeedsManualMerge,
+            hunks_merged: Some(2),
+            hunks_conflicted: Some(1),
+            output: PathBuf::from("Patch_bad"),
+            diff: None,
+            hunks: vec![HunkReport {mod_name: "C".to_owned(), line: 12, applied: false, offset: 0, fuzz: 0}],
+        }]);
+        let json = report.to_json().unwrap();
+        assert_eq!(MergeReport::from_json(&json).unwrap(), report);
+        assert_eq!(report.successful(), 0);
+
+        let html = report.to_html();
+        assert!(html.contains("Patch &lt;1&gt;"));
+        assert!(html.contains("A &amp; B<br>C"));
+        assert!(html.contains("C line 12: refused"));
+    }
+}
@@ -0,0 +1,264 @@
+use std::path::{Path,PathBuf};
+use std::collections::BTreeMap;
+
+use fxhash::hash64;
+use serde::{Deserialize,Serialize};
+
+use crate::configs;
+use crate::io::files;
+use crate::merge_diff::{ConflictHunk,MergeChunk};
+
+/// Name of the file that resolutions are saved in, next to merger.toml
+pub const RESOLUTIONS_NAME: &str = "resolutions.json";
+
+/// Which side of a conflicting hunk was kept
+#[derive(Serialize,Deserialize,Clone,Debug,PartialEq,Eq)]
+pub enum ResolutionChoice {
+    /// The vanilla lines, dropping every mod's change
+    Vanilla,
+    /// The version of the named mod
+    Mod(String),
+    /// Every mod's version, one after the other in load order
+    AllInOrder,
+    /// Lines written by hand
+    Edited,
+}
+
+/// A rule that settles every conflicting hunk the same way, without asking
+#[derive(Serialize,Deserialize,Clone,Copy,Debug,PartialEq,Eq)]
+pub enum ResolutionPolicy {
+    /// Keep the vanilla lines
+    Vanilla,
+    /// Keep the version of the first mod in load order that changed the lines
+    FirstMod,
+    /// Keep the version of the last mod in load order that changed the lines, as the game itself would
+    LastMod,
+    /// Keep every mod's version, one after the other in load order
+    AllInOrder,
+}
+
+impl ResolutionPolicy {
+    /// Resolves a hunk by this policy, `mods` naming the mod behind each version of the hunk
+    pub fn resolve(self, hunk: &ConflictHunk, mods: &[String]) -> Resolution {
+        let mut changed = mods.iter().zip(&hunk.versions).filter_map(|(name,version)| Some((name,version.as_ref()?)));
+        let picked = match self {
+            ResolutionPolicy::FirstMod => changed.next(),
+            ResolutionPolicy::LastMod => changed.next_back(),
+            ResolutionPolicy::Vanilla | ResolutionPolicy::AllInOrder => None,
+        };
+        let (choice,lines) = match (self,picked) {
+            (ResolutionPolicy::AllInOrder,_) => (ResolutionChoice::AllInOrder, hunk.all_in_order()),
+            (_,Some((name,lines))) => (ResolutionChoice::Mod(name.to_owned()), lines.clone()),
+            _ => (ResolutionChoice::Vanilla, hunk.base.clone()),
+        };
+        Resolution {choice, lines, policy: Some(self)}
+    }
+}
+
+/// How a single conflicting hunk was settled
+#[derive(Serialize,Deserialize,Clone,Debug,PartialEq,Eq)]
+pub struct Resolution {
+    pub choice: ResolutionChoice,
+    /// Lines that replace the hunk in the merged file
+    pub lines: Vec<String>,
+    /// The policy that chose the lines, None if they were chosen by hand
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub policy: Option<ResolutionPolicy>,
+}
+
+/// Resolutions chosen for conflicting hunks, so the same clash is settled the same way on every run
+#[derive(Serialize,Deserialize,Clone,Debug,Default,PartialEq,Eq)]
+pub struct ResolutionStore {
+    /// File path, then the hash of the hunk, then how it was resolved
+    files: BTreeMap<PathBuf,BTreeMap<String,Resolution>>,
+}
+
+impl ResolutionStore {
+    /// Default location of the resolutions file, beside the user's configuration
+    pub fn default_path() -> PathBuf {
+        configs::user_config_sibling(RESOLUTIONS_NAME)
+    }
+
+    /// Reads the resolutions file, returning an empty store if it does not exist yet
+    pub fn open(path: &Path) -> Result<Self,Box<dyn std::error::Error>> {
+        if !path.exists() {
+            return Ok(ResolutionStore::default());
+        }
+        let contents = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&contents)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(),Box<dyn std::error::Error>> {
+        files::write_file_with_string(path, serde_json::to_string_pretty(self)?, false)?;
+        Ok(())
+    }
+
+    /// The saved resolution of a hunk in a file, if it was resolved before
+    pub fn get(&self, path: &Path, hunk: &ConflictHunk) -> Option<&Resolution> {
+        self.files.get(path)?.get(&hunk_hash(hunk))
+    }
+
+    /// Saves how a hunk was resolved, replacing any earlier resolution of the same hunk
+    pub fn insert(&mut self, path: &Path, hunk: &ConflictHunk, resolution: Resolution) {
+        self.files.entry(path.to_path_buf()).or_default().insert(hunk_hash(hunk), resolution);
+    }
+
+    /// Every file with saved resolutions, along with how many hunks in it were resolved
+    pub fn files(&self) -> Vec<(&Path,usize)> {
+        self.files.iter().map(|(path,hunks)| (path.as_path(),hunks.len())).collect()
+    }
+
+    /// Number of saved resolutions across every file
+    pub fn len(&self) -> usize {
+        self.files.values().map(|hunks| hunks.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// Forgets every resolution saved for a file, returning how many there were
+    pub fn remove_file(&mut self, path: &Path) -> usize {
+        self.files.remove(path).map(|hunks| hunks.len()).unwrap_or(0)
+    }
+
+    /// Adds the resolutions of another store, such as one exported by a teammate
+    /// Hunks resolved in both are only replaced if `overwrite` is set
+    /// Returns how many resolutions were added and how many were replaced
+    pub fn import(&mut self, other: ResolutionStore, overwrite: bool) -> (usize,usize) {
+        let mut added = 0;
+        let mut replaced = 0;
+        for (path,hunks) in other.files {
+            let ours = self.files.entry(path).or_default();
+            for (hash,resolution) in hunks {
+                match ours.get(&hash) {
+                    None => added += 1,
+                    Some(existing) if overwrite && *existing != resolution => replaced += 1,
+                    Some(_) => continue,
+                }
+                ours.insert(hash, resolution);
+            }
+        }
+        (added,replaced)
+    }
+
+    /// Hunks of a line based merge that have no saved resolution
+    pub fn unresolved<'a>(&self, path: &Path, chunks: &'a [MergeChunk]) -> Vec<&'a ConflictHunk> {
+        chunks.iter().filter_map(|chunk| match chunk {
+            MergeChunk::Conflict(hunk) if self.get(path, hunk).is_none() => Some(hunk),
+            _ => None,
+        }).collect()
+    }
+
+    /// Lines of a line based merge with every conflicting hunk replaced by its saved resolution
+    /// Returns None if any hunk has not been resolved yet, or if no hunk clashed so there was nothing to resolve
+    /// The automatic merge refuses some files without any clashing hunk, and those still need merging by hand
+    pub fn apply(&self, path: &Path, chunks: &[MergeChunk]) -> Option<Vec<String>> {
+        let mut lines = Vec::new();
+        let mut resolved = false;
+        for chunk in chunks {
+            match chunk {
+                MergeChunk::Clean(clean) => lines.extend(clean.iter().cloned()),
+                MergeChunk::Conflict(hunk) => {
+                    lines.extend(self.get(path, hunk)?.lines.iter().cloned());
+                    resolved = true;
+                },
+            }
+        }
+        if resolved { Some(lines) } else { None }
+    }
+}
+
+/// A conflicting file that the automatic merge could not settle, split into clean runs and clashing hunks
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub struct UnresolvedConflict {
+    /// Path of the file relative to the game directory
+    pub path: PathBuf,
+    /// Name of the mod behind each version in the hunks, in load order
+    pub mods: Vec<String>,
+    pub chunks: Vec<MergeChunk>,
+}
+
+/// Identifies a conflicting hunk by its vanilla lines and the changed versions, ignoring which mods made them
+/// Kept on fxhash rather than the manifest's digest so resolutions saved by earlier versions still apply
+pub fn hunk_hash(hunk: &ConflictHunk) -> String {
+    let mut key = hunk.base.join("\n");
+    for version in hunk.versions.iter().flatten() {
+        key.push('\0');
+        key.push_str(&version.join("\n"));
+    }
+    format!("{:016x}", hash64(key.as_bytes()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::merge_diff::{diff_single_conflict_with,merge_lines,DiffOptions};
+
+    #[test]
+    fn test_apply_saved_resolutions() {
+        let path = Path::new("common/t.txt");
+        let chunks = merge_lines("a\nb\nc\n", &["a\nB\nc\n".to_owned(), "a\nb2\nc\n".to_owned()]);
+        let mut store = ResolutionStore::default();
+        assert_eq!(store.unresolved(path, &chunks).len(), 1);
+        assert_eq!(store.apply(path, &chunks), None);
+
+        let hunk = store.unresolved(path, &chunks)[0].clone();
+        store.insert(path, &hunk, Resolution {choice: ResolutionChoice::AllInOrder, lines: hunk.all_in_order(), policy: None});
+        assert_eq!(store.apply(path, &chunks).unwrap(), vec!["a","B","b2","c"]);
+        assert_eq!(store.apply(Path::new("common/other.txt"), &chunks), None);
+
+        let json = serde_json::to_string(&store).unwrap();
+        assert_eq!(serde_json::from_str::<ResolutionStore>(&json).unwrap(), store);
+    }
+
+    #[test]
+    fn test_nothing_to_apply_without_clashing_hunks() {
+        // A change right after lines another mod rewrote has no context left, so the automatic merge refuses it
+        // Line by line the two changes don't overlap, so there is no hunk a resolution could settle
+        let path = Path::new("common/t.txt");
+        let lines: Vec<String> = (1..=12).map(|i| format!("key_{} = {}",i,i)).collect();
+        let mut rewrite_block = lines.clone();
+        for line in rewrite_block.iter_mut().take(8).skip(4) {
+            *line = line.replace("key", "other");
+        }
+        let mut next_line = lines.clone();
+        next_line[8] = "key_9 = changed".to_owned();
+        let vanilla = lines.join("\r\n") + "\r\n";
+        let mods = [rewrite_block.join("\r\n") + "\r\n", next_line.join("\r\n") + "\r\n"];
+        assert_eq!(diff_single_conflict_with(&vanilla, &mods, &DiffOptions::default()).text, None);
+
+        let chunks = merge_lines(&vanilla, &mods);
+        let store = ResolutionStore::default();
+        assert!(store.unresolved(path, &chunks).is_empty());
+        assert_eq!(store.apply(path, &chunks), None);
+    }
+
+    #[test]
+    fn test_policies_and_import() {
+        let path = Path::new("common/t.txt");
+        let chunks = merge_lines("a\nb\nc\n", &["a\nb\nc\n".to_owned(), "a\nB\nc\n".to_owned(), "a\nb2\nc\n".to_owned()]);
+        let hunk = match &chunks[1] {
+            MergeChunk::Conflict(hunk) => hunk.clone(),
+            other => panic!("Expected a conflict, found {:?}", other),
+        };
+        let mods = vec!["Untouched".to_owned(), "First".to_owned(), "Second".to_owned()];
+        let first = ResolutionPolicy::FirstMod.resolve(&hunk, &mods);
+        assert_eq!((first.choice,first.lines), (ResolutionChoice::Mod("First".to_owned()), vec!["B".to_owned()]));
+        assert_eq!(ResolutionPolicy::LastMod.resolve(&hunk, &mods).lines, vec!["b2"]);
+        assert_eq!(ResolutionPolicy::Vanilla.resolve(&hunk, &mods).lines, vec!["b"]);
+        assert_eq!(ResolutionPolicy::AllInOrder.resolve(&hunk, &mods).lines, vec!["B","b2"]);
+
+        let mut ours = ResolutionStore::default();
+        ours.insert(path, &hunk, ResolutionPolicy::FirstMod.resolve(&hunk, &mods));
+        let mut theirs = ResolutionStore::default();
+        theirs.insert(path, &hunk, ResolutionPolicy::LastMod.resolve(&hunk, &mods));
+        theirs.insert(Path::new("common/other.txt"), &hunk, ResolutionPolicy::Vanilla.resolve(&hunk, &mods));
+
+        assert_eq!(ours.clone().import(theirs.clone(), false), (1,0));
+        assert_eq!(ours.import(theirs, true), (1,1));
+        assert_eq!(ours.apply(path, &chunks).unwrap(), vec!["a","b2","c"]);
+        assert_eq!(ours.len(), 2);
+        assert_eq!(ours.remove_file(path), 1);
+    }
+}
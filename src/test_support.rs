@@ -0,0 +1,200 @@
+//! Builds a throwaway game install and user directory, so the whole pipeline can be tested without a real game
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path,PathBuf};
+
+use crate::configs::{ArgOptions,ConfigOptions};
+use crate::io::{files,zips};
+
+/// An empty directory under the temp directory, removed again when dropped
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    /// Creates the directory, replacing anything left by an earlier run
+    /// # Arguments
+    ///
+    /// * `name` - unique name of the test, so tests running in parallel never share a directory
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("paradoxmerger-{}-{}",name,std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TempDir {path}
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn join<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+        self.path.join(path)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+/// Seeded random numbers, so randomized tests check the same cases on every run
+pub struct Lcg(pub u64);
+
+impl Lcg {
+    /// The next number, from zero up to but not including `bound`
+    pub fn below(&mut self, bound: usize) -> usize {
+        self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        ((self.0 >> 33) as usize) % bound
+    }
+}
+
+/// A temporary game install and user directory, removed again when dropped
+/// Vanilla files go under `game`, while mods, their descriptors and the launcher settings go under `user`
+pub struct FakeGame {
+    root: TempDir,
+    new_launcher: bool,
+    enabled: Vec<String>,
+}
+
+impl FakeGame {
+    /// Creates an empty install in the temp directory, replacing anything left by an earlier run
+    /// # Arguments
+    ///
+    /// * `name` - unique name of the test, so tests running in parallel never share a directory
+    ///
+    /// * `new_launcher` - if yes, enabled mods are written to dlc_load.json, otherwise to settings.txt
+    pub fn new(name: &str, new_launcher: bool) -> Self {
+        let root = TempDir::new(name);
+        fs::create_dir_all(root.join("game")).unwrap();
+        fs::create_dir_all(root.join("user").join("mod")).unwrap();
+        let game = FakeGame {root, new_launcher, enabled: Vec::new()};
+        game.write_settings();
+        game
+    }
+
+    pub fn game_dir(&self) -> PathBuf {
+        self.root.join("game")
+    }
+
+    pub fn user_dir(&self) -> PathBuf {
+        self.root.join("user")
+    }
+
+    /// Directory patches are written into, kept apart from the mods themselves
+    pub fn output_dir(&self) -> PathBuf {
+        self.root.join("output")
+    }
+
+    /// Adds a file to the game's own data
+    pub fn vanilla(self, path: &str, contents: &str) -> Self {
+        files::write_file_with_string(self.game_dir().join(path), contents.to_owned(), false).unwrap();
+        self
+    }
+
+    /// Adds a mod whose files sit in a folder, with a descriptor pointing at it with `path =`
+    /// # Arguments
+    ///
+    /// * `name` - name of the mod, its descriptor and folder are named after it in lower case
+    ///
+    /// * `mod_files` - relative path and contents of every file in the mod
+    pub fn folder_mod(self, name: &str, mod_files: &[(&str,&str)]) -> Self {
+        let folder = name.to_ascii_lowercase();
+        for (path,contents) in mod_files {
+            files::write_file_with_string(self.user_dir().join("mod").join(&folder).join(path), (*contents).to_owned(), false).unwrap();
+        }
+        self.descriptor(name, &format!("path = \"mod/{}\"",folder), &[])
+    }
+
+    /// Adds a mod whose files are packed in a zip archive, with a descriptor pointing at it with `archive =`
+    /// # Arguments
+    ///
+    /// * `name` - name of the mod, its descriptor and archive are named after it in lower case
+    ///
+    /// * `mod_files` - relative path and contents of every file in the archive
+    pub fn zip_mod(self, name: &str, mod_files: &[(&str,&str)]) -> Self {
+        let archive = format!("{}.zip",name.to_ascii_lowercase());
+        let mut zip_output = zips::ZipStreamWriter::create(&self.user_dir().join("mod").join(&archive)).unwrap();
+        for (path,contents) in mod_files {
+            assert!(zip_output.start_entry(path).unwrap());
+            zip_output.write_all(contents.as_bytes()).unwrap();
+        }
+        zip_output.finish().unwrap();
+        self.descriptor(name, &format!("archive = \"mod/{}\"",archive), &[])
+    }
+
+    /// Writes the descriptor of a mod, as `mod/<name>.mod` in lower case
+    /// # Arguments
+    ///
+    /// * `name` - name of the mod
+    ///
+    /// * `location` - the `path =` or `archive =` line of the descriptor
+    ///
+    /// * `dependencies` - names of the mods it must load after
+    pub fn descriptor(self, name: &str, location: &str, dependencies: &[&str]) -> Self {
+        let mut contents = format!("name = \"{}\"\n{}\n",name,location);
+        if !dependencies.is_empty() {
+            contents.push_str("dependencies = {\n");
+            for dependency in dependencies {
+                contents.push_str(&format!("\"\\\"{}\\\"\"\n",dependency));
+            }
+            contents.push_str("}\n");
+        }
+        files::write_file_with_string(self.user_dir().join(Self::descriptor_file(name)), contents, false).unwrap();
+        self
+    }
+
+    /// Enables the named mods in the launcher settings, in the given order
+    pub fn enable(mut self, names: &[&str]) -> Self {
+        self.enabled = names.iter().map(|name| Self::descriptor_file(name)).collect();
+        self.write_settings();
+        self
+    }
+
+    /// Game configuration pointing at this install, merging .txt files under the given folders
+    pub fn config(&self, valid_paths: &[&str]) -> ConfigOptions {
+        ConfigOptions::new_with_str("Test".to_owned(), self.user_dir(), self.game_dir(), valid_paths, &["txt"], &[], self.new_launcher)
+    }
+
+    /// Arguments writing a patch of the given name into the output directory, on a single thread
+    pub fn args(&self, patch_name: &str, extract: bool) -> ArgOptions {
+        ArgOptions::new(self.root.join("merger.toml"), extract, false, false, "Test".to_owned(), patch_name.to_owned())
+            .with_jobs(1)
+            .with_output_dir(self.output_dir())
+    }
+
+    /// Contents of a file written into the output directory, line endings included as they were written
+    pub fn output(&self, path: &str) -> Option<String> {
+        files::fetch_file_in_path(&self.output_dir().join(path), false, false)
+    }
+
+    fn descriptor_file(name: &str) -> String {
+        format!("mod/{}.mod",name.to_ascii_lowercase())
+    }
+
+    fn write_settings(&self) {
+        if self.new_launcher {
+            let settings = serde_json::json!({"enabled_mods": self.enabled, "disabled_dlcs": []});
+            files::write_file_with_string(self.user_dir().join("dlc_load.json"), settings.to_string(), false).unwrap();
+        } else {
+            let mut settings = String::from("language=\"l_english\"\r\nlast_mods=\r\n{\r\n");
+            for mod_file in &self.enabled {
+                settings.push_str(&format!("\"{}\"\r\n",mod_file));
+            }
+            settings.push_str("}\r\nlast_dlcs=\r\n{\r\n}\r\n");
+            files::write_file_with_string(self.user_dir().join("settings.txt"), settings, false).unwrap();
+        }
+    }
+}
+
+/// Lists every entry of a zip archive with its contents, sorted by name
+pub fn zip_entries(zip_path: &Path) -> Vec<(String,String)> {
+    let mut archive = zips::zip_open(zip_path).unwrap();
+    let mut names: Vec<String> = archive.file_names().map(|name| name.to_owned()).collect();
+    names.sort();
+    names.into_iter().map(|name| {
+        let contents = zips::zip_read_entry(&mut archive, &name).unwrap();
+        (name, String::from_utf8(contents).unwrap())
+    }).collect()
+}
@@ -0,0 +1,137 @@
+// Line based diffs between whole files, written out in unified diff format for people to review
+use unidiff::{Hunk,Line,PatchedFile};
+
+use crate::line_diff::{diff_lines,split_lines,LineOp};
+
+/// Describes the differences between two texts as a patched file, or None if they are the same
+/// # Arguments
+///
+/// * `old_label` - name of the old file in the `---` header
+///
+/// * `new_label` - name of the new file in the `+++` header
+///
+/// * `old` - original text
+///
+/// * `new` - changed text
+///
+/// * `context` - number of unchanged lines to show around each change
+pub fn patched_file(old_label: &str, new_label: &str, old: &str, new: &str, context: usize) -> Option<PatchedFile> {
+    let old_lines = split_lines(old);
+    let new_lines = split_lines(new);
+    let ops = diff_lines(&old_lines, &new_lines);
+    if ops.iter().all(|op| matches!(op, LineOp::Equal(..))) {
+        return None;
+    }
+
+    let mut hunks = Vec::new();
+    for (start,end) in hunk_ranges(&ops, context) {
+        let ops_in_hunk = &ops[start..end];
+        let old_len = ops_in_hunk.iter().filter(|op| !matches!(op, LineOp::Insert(_))).count();
+        let new_len = ops_in_hunk.iter().filter(|op| !matches!(op, LineOp::Delete(_))).count();
+        let old_start = hunk_start(first_line(&ops[..start], true), old_len);
+        let new_start = hunk_start(first_line(&ops[..start], false), new_len);
+        let mut hunk = Hunk::new(old_start, old_len, new_start, new_len, "");
+        for op in ops_in_hunk {
+            hunk.append(match op {
+                LineOp::Equal(a,_) => Line::new(old_lines[*a], " "),
+                LineOp::Delete(a) => Line::new(old_lines[*a], "-"),
+                LineOp::Insert(b) => Line::new(new_lines[*b], "+"),
+            });
+        }
+        hunks.push(hunk);
+    }
+    Some(PatchedFile::with_hunks(old_label, new_label, hunks))
+}
+
+/// Writes the differences between two texts as a unified diff, or an empty string if they are the same
+/// Takes the same arguments as `patched_file`
+pub fn unified_diff(old_label: &str, new_label: &str, old: &str, new: &str, context: usize) -> String {
+    match patched_file(old_label, new_label, old, new, context) {
+        Some(file) => format!("{}\n", file),
+        None => String::new(),
+    }
+}
+
+/// Writes a patch for reviewing a merged file, with the merged output against vanilla and then against each mod
+/// # Arguments
+///
+/// * `path` - path of the file relative to the game directory, used in the file headers
+///
+/// * `vanilla` - the game's version of the file
+///
+/// * `mods` - name and contents of each mod's version, in load order
+///
+/// * `merged` - the merged output
+///
+/// * `context` - number of unchanged lines to show around each change
+pub fn review_patch(path: &str, vanilla: &str, mods: &[(&str,&str)], merged: &str, context: usize) -> String {
+    let new_label = format!("b/merged/{}", path);
+    let sources = std::iter::once(("vanilla",vanilla)).chain(mods.iter().copied());
+    sources.filter_map(|(name,old)| patched_file(&format!("a/{}/{}", name, path), &new_label, old, merged, context))
+        .map(|file| format!("{}\n", file))
+        .collect()
+}
+
+// Groups changes into hunks, joining changes separated by no more than twice the context
+fn hunk_ranges(ops: &[LineOp], context: usize) -> Vec<(usize,usize)> {
+    let changes: Vec<usize> = ops.iter().enumerate().filter(|(_,op)| !matches!(op, LineOp::Equal(..))).map(|(i,_)| i).collect();
+    let mut ranges: Vec<(usize,usize)> = Vec::new();
+    for change in changes {
+        let start = change.saturating_sub(context);
+        let end = (change + context + 1).min(ops.len());
+        match ranges.last_mut() {
+            Some(last) if start <= last.1 => last.1 = end,
+            _ => ranges.push((start,end)),
+        }
+    }
+    ranges
+}
+
+// Number of old or new lines before a hunk
+fn first_line(before: &[LineOp], old: bool) -> usize {
+    before.iter().filter(|op| match op {
+        LineOp::Equal(..) => true,
+        LineOp::Delete(_) => old,
+        LineOp::Insert(_) => !old,
+    }).count()
+}
+
+fn hunk_start(lines_before: usize, len: usize) -> usize {
+    // Empty ranges point at the line before them, as in GNU diff
+    if len == 0 { lines_before } else { lines_before + 1 }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_unified_diff_format() {
+        let old = "one\r\ntwo\r\nthree\r\nfour\r\nfive\r\nsix\r\nseven\r\neight\r\nnine\r\n";
+        let new = "one\r\ntwo\r\nthree\r\nFOUR\r\nfive\r\nsix\r\nseven\r\neight\r\nnine\r\nten\r\n";
+        let expected = "--- a/t.txt\n+++ b/t.txt\n@@ -1,9 +1,10 @@ \n one\n two\n three\n-four\n+FOUR\n five\n six\n seven\n eight\n nine\n+ten\n";
+        assert_eq!(unified_diff("a/t.txt", "b/t.txt", old, new, 3), expected);
+        assert_eq!(unified_diff("a/t.txt", "b/t.txt", old, old, 3), "");
+
+        // The output must be readable by other unified diff tools
+        let mut patch = unidiff::PatchSet::new();
+        patch.parse(expected).unwrap();
+        assert_eq!((patch[0].added(),patch[0].removed()), (2,1));
+    }
+
+    #[test]
+    fn test_review_patch() {
+        let vanilla = "a\nb\nc\n";
+        let merged = "a\nB\nc\nd\n";
+        let mods = [("First","a\nB\nc\n"), ("Second","a\nb\nc\nd\n"), ("Same","a\nB\nc\nd\n")];
+        let mut patch = unidiff::PatchSet::new();
+        patch.parse(review_patch("common/t.txt", vanilla, &mods, merged, 3)).unwrap();
+
+        // A mod that matches the merged output adds nothing to review
+        let sources: Vec<String> = (0..patch.len()).map(|i| patch[i].source_file.clone()).collect();
+        assert_eq!(sources, vec!["a/vanilla/common/t.txt","a/First/common/t.txt","a/Second/common/t.txt"]);
+        assert_eq!((patch[0].added(),patch[0].removed()), (2,1));
+        assert_eq!((patch[1].added(),patch[1].removed()), (1,0));
+        assert_eq!((patch[2].added(),patch[2].removed()), (1,1));
+    }
+}